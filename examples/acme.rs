@@ -52,8 +52,8 @@ fn main() {
 
     /* create a client, register the metrics with it, and export them */
 
-    Client::new("acme").unwrap()
-        .export(&mut [&mut counts, &mut times, &mut queue_times]).unwrap();
+    let client = Client::new("acme").unwrap();
+    client.export(&mut [&mut counts, &mut times, &mut queue_times]).unwrap();
 
     /* update metrics */
 
@@ -64,17 +64,17 @@ fn main() {
         thread::sleep(Duration::from_secs(working_time));
 
         let count = counts.val(product).unwrap();
-        counts.set_val(product, count + 1).unwrap().unwrap();
+        counts.set_val(product, count + 1).unwrap();
 
         let time = times.val(product).unwrap();
-        times.set_val(product, time + 1).unwrap().unwrap();
+        times.set_val(product, time + 1).unwrap();
 
         for i in 0..products.len() {
             if i != rnd_idx {
                 let queued_product = products[i];
 
                 let queue_time = queue_times.val(queued_product).unwrap();
-                queue_times.set_val(queued_product, queue_time + 1).unwrap().unwrap();
+                queue_times.set_val(queued_product, queue_time + 1).unwrap();
             }
         }
     }
@@ -41,8 +41,8 @@ fn main() {
 
     /* create a client, register the metrics with it, and export them */
 
-    Client::new("physical_metrics").unwrap()
-        .export(&mut [&mut freq, &mut color, &mut photons]).unwrap();
+    let client = Client::new("physical_metrics").unwrap();
+    client.export(&mut [&mut freq, &mut color, &mut photons]).unwrap();
 
     /* update metric values */
 
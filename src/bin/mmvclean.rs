@@ -0,0 +1,32 @@
+extern crate hornet;
+
+use hornet::mmv;
+use std::env;
+use std::path::Path;
+use std::process;
+
+fn main() {
+    // defaults to the directory clients export MMVs to
+    let cleanup = match env::args().nth(1) {
+        Some(dir_arg) => mmv::clean(Path::new(&dir_arg)),
+        None => mmv::clean_mmv_dir()
+    };
+
+    match cleanup {
+        Ok(cleanup) => {
+            for path in cleanup.removed() {
+                println!("removed {}", path.display());
+            }
+            for &(ref path, ref err) in cleanup.errors() {
+                eprintln!("couldn't remove {}: {}", path.display(), err);
+            }
+            if !cleanup.errors().is_empty() {
+                process::exit(1);
+            }
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
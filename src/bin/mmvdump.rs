@@ -3,11 +3,18 @@ extern crate hornet;
 use hornet::mmv;
 use std::env;
 use std::path::Path;
+use std::process;
 
 fn main() {
     let path_arg = env::args().nth(1)
         .expect("Specify path to mmv file");
     let mmv_path = Path::new(&path_arg);
 
-    print!("{}", mmv::dump(&mmv_path).unwrap());
+    match mmv::dump(&mmv_path) {
+        Ok(mmv) => print!("{}", mmv),
+        Err(err) => {
+            eprintln!("{}: {}", mmv_path.display(), err);
+            process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,159 @@
+use std::sync::Mutex;
+
+use super::super::Error;
+use super::{lock_export, Client, Export};
+use super::metric::{InstanceMetric, Metric, MetricType};
+
+/// A batch of updates to exported metrics, that're applied together
+///
+/// Updates are staged with `set`, `set_instance` and `update`, and are
+/// only applied when the batch is committed. While they're applied, the
+/// generation numbers in the MMV header mismatch, and once they're applied,
+/// the header gets a new generation. Readers that check the generations
+/// before and after reading values thereby see either none or all of the
+/// updates.
+///
+/// The client isn't locked while the updates are applied, so staged
+/// updates can add or remove instances, or register metrics. The MMV
+/// that's regenerated then stays locked until the batch is committed.
+pub struct Batch<'a> {
+    client: &'a Client,
+    updates: Vec<Box<FnMut() -> Result<(), Error> + 'a>>
+}
+
+impl<'a> Batch<'a> {
+    pub (super) fn new(client: &'a Client) -> Self {
+        Batch {
+            client: client,
+            updates: Vec::new()
+        }
+    }
+
+    /// Stages setting the value of a metric
+    pub fn set<T: MetricType + Clone>(&mut self, metric: &'a Metric<T>, val: T) -> &mut Self {
+        self.updates.push(Box::new(move || Ok(metric.set_val(val.clone())?)));
+        self
+    }
+
+    /// Stages setting the value of an instance of an instance metric
+    pub fn set_instance<T: MetricType + Clone>(&mut self, metric: &'a InstanceMetric<T>,
+        instance: &str, val: T) -> &mut Self {
+        let instance = instance.to_owned();
+        self.updates.push(Box::new(move || metric.set_val(&instance, val.clone())));
+        self
+    }
+
+    /// Stages an arbitrary update, e.g., incrementing a `Counter`
+    pub fn update<F>(&mut self, f: F) -> &mut Self
+        where F: FnMut() -> Result<(), Error> + 'a {
+        self.updates.push(Box::new(f));
+        self
+    }
+
+    /// Returns the number of staged updates
+    pub fn len(&self) -> usize {
+        self.updates.len()
+    }
+
+    /// Checks if no updates are staged
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Applies the staged updates in the order they were staged
+    ///
+    /// If an update fails, the remaining ones aren't applied, but the
+    /// ones already applied are still published with a new generation.
+    pub fn commit(self) -> Result<(), Error> {
+        lock_export(&self.client.export).begin_batch();
+        let _committing = Committing(&self.client.export);
+        for mut update in self.updates {
+            update()?;
+        }
+        Ok(())
+    }
+}
+
+// ends a batch when it's dropped, even if an update panics
+struct Committing<'a>(&'a Mutex<Export>);
+
+impl<'a> Drop for Committing<'a> {
+    fn drop(&mut self) {
+        lock_export(self.0).end_batch();
+    }
+}
+
+#[test]
+fn test() {
+    use super::super::mmv::dump;
+    use super::metric::{Counter, Indom, Semantics, Unit};
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut bytes_in = Metric::new("batch_bytes_in", 0u64, Semantics::Counter, Unit::new(), "", "").unwrap();
+    let indom = Indom::new(&["a", "b"], "", "").unwrap();
+    let mut im = InstanceMetric::new(&indom, "batch_instances", 0i32, Semantics::Instant, Unit::new(), "", "").unwrap();
+    let mut requests = Counter::new("batch_requests", 0, "", "").unwrap();
+
+    let client = Client::new("batch_test").unwrap();
+    client.export(&mut [&mut bytes_in, &mut im, &mut requests]).unwrap();
+    let gen = dump(client.mmv_path()).unwrap().header().gen1();
+
+    let read_gens = || {
+        let mut header = [0u8; 24];
+        File::open(client.mmv_path()).unwrap().read_exact(&mut header).unwrap();
+        let gen = |off: usize| (0..8).fold(0i64, |gen, i| gen | (header[off + i] as i64) << (8 * i));
+        (gen(8), gen(16))
+    };
+
+    let mut batch = client.batch();
+    batch.set(&bytes_in, 512)
+        .set_instance(&im, "b", -3)
+        .update(|| {
+            // the header is locked while updates are applied
+            let (gen1, gen2) = read_gens();
+            assert_eq!(gen1, gen);
+            assert_eq!(gen2, 0);
+            Ok(requests.up()?)
+        });
+    assert_eq!(batch.len(), 3);
+    assert_eq!(bytes_in.val(), 0);
+    batch.commit().unwrap();
+
+    assert_eq!(bytes_in.val(), 512);
+    assert_eq!(im.val("b"), Some(-3));
+    assert_eq!(requests.val(), 1);
+
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert!(mmv.header().gen1() > gen);
+    assert_eq!(mmv.header().gen1(), mmv.header().gen2());
+
+    let mut batch = client.batch();
+    batch.set_instance(&im, "c", 1).set(&bytes_in, 0);
+    assert!(batch.commit().is_err());
+    assert_eq!(bytes_in.val(), 512);
+    let (gen1, gen2) = read_gens();
+    assert_eq!(gen1, gen2);
+
+    // the MMV can be regenerated while a batch is committed, and stays
+    // locked until it's done
+    let gen = gen1;
+    let mut new_counter = Counter::new("batch_new_counter", 0, "", "").unwrap();
+    let mut batch = client.batch();
+    batch.update(|| im.add_instance("c", 7))
+        .update(|| client.register(&mut new_counter))
+        .update(|| {
+            let (gen1, gen2) = read_gens();
+            assert!(gen1 > gen);
+            assert_eq!(gen2, 0);
+            Ok(())
+        })
+        .set_instance(&im, "c", 8);
+    batch.commit().unwrap();
+
+    assert_eq!(im.val("c"), Some(8));
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert!(mmv.header().gen1() > gen);
+    assert_eq!(mmv.header().gen1(), mmv.header().gen2());
+    assert_eq!(mmv.metric_blks().len(), 4);
+}
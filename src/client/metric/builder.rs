@@ -0,0 +1,154 @@
+use super::*;
+
+/// Builder for metrics and instance metrics
+///
+/// Defaults to `Semantics::Instant`, an empty unit, empty help texts,
+/// and an item ID derived from the metric's name. Setting an instance
+/// domain with `indom` makes the builder build an `InstanceMetric`
+/// instead of a `Metric`.
+///
+/// The metric is validated only when it's built.
+pub struct MetricBuilder<T, I = ()> {
+    name: String,
+    init_val: T,
+    sem: Semantics,
+    unit: Unit,
+    shorthelp: String,
+    longhelp: String,
+    item: Option<u32>,
+    indom: I
+}
+
+impl<T: MetricType + Clone> MetricBuilder<T> {
+    /// Creates a builder for a metric with given name and initial value
+    pub fn new(name: &str, init_val: T) -> Self {
+        MetricBuilder {
+            name: name.to_owned(),
+            init_val: init_val,
+            sem: Semantics::Instant,
+            unit: Unit::new(),
+            shorthelp: String::new(),
+            longhelp: String::new(),
+            item: None,
+            indom: ()
+        }
+    }
+
+    /// Sets the instance domain, so that an instance metric is built
+    pub fn indom(self, indom: &Indom) -> MetricBuilder<T, Indom> {
+        MetricBuilder {
+            name: self.name,
+            init_val: self.init_val,
+            sem: self.sem,
+            unit: self.unit,
+            shorthelp: self.shorthelp,
+            longhelp: self.longhelp,
+            item: self.item,
+            indom: indom.clone()
+        }
+    }
+
+    /// Builds the metric
+    ///
+    /// The result is an error if the name or help texts are too long,
+    /// or if the item ID doesn't fit in 10 bits.
+    pub fn build(self) -> Result<Metric<T>, Error> {
+        let mut metric = Metric::new(
+            &self.name, self.init_val, self.sem, self.unit,
+            &self.shorthelp, &self.longhelp
+        )?;
+        if let Some(item) = self.item {
+            metric.set_item(item)?;
+        }
+        Ok(metric)
+    }
+}
+
+impl<T: MetricType + Clone> MetricBuilder<T, Indom> {
+    /// Builds the instance metric, with every instance
+    /// holding the initial value
+    ///
+    /// The result is an error if the name or help texts are too long,
+    /// or if the item ID doesn't fit in 10 bits.
+    pub fn build(self) -> Result<InstanceMetric<T>, Error> {
+        let mut im = InstanceMetric::new(
+            &self.indom, &self.name, self.init_val, self.sem, self.unit,
+            &self.shorthelp, &self.longhelp
+        )?;
+        if let Some(item) = self.item {
+            im.set_item(item)?;
+        }
+        Ok(im)
+    }
+}
+
+impl<T, I> MetricBuilder<T, I> {
+    /// Sets the semantics
+    pub fn semantics(mut self, sem: Semantics) -> Self {
+        self.sem = sem;
+        self
+    }
+
+    /// Sets the unit
+    pub fn unit(mut self, unit: Unit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    /// Sets the short help text
+    pub fn shorthelp(mut self, shorthelp: &str) -> Self {
+        self.shorthelp = shorthelp.to_owned();
+        self
+    }
+
+    /// Sets the long help text
+    pub fn longhelp(mut self, longhelp: &str) -> Self {
+        self.longhelp = longhelp.to_owned();
+        self
+    }
+
+    /// Sets an explicit item ID, instead of one derived from the name
+    pub fn item(mut self, item: u32) -> Self {
+        self.item = Some(item);
+        self
+    }
+}
+
+#[test]
+fn test() {
+    let metric = MetricBuilder::new("builder_metric", 1u32).build().unwrap();
+    assert_eq!(metric.val(), 1);
+    assert_eq!(*metric.sem() as u32, Semantics::Instant as u32);
+    assert_eq!(metric.unit(), 0);
+    assert_eq!(metric.shorthelp(), "");
+    assert_eq!(metric.longhelp(), "");
+
+    let unit = Unit::new().count(Count::One, 1).unwrap();
+    let metric = MetricBuilder::new("builder_metric", 1.5)
+        .semantics(Semantics::Counter)
+        .unit(unit)
+        .shorthelp("short")
+        .longhelp("long")
+        .item(10)
+        .build().unwrap();
+    assert_eq!(*metric.sem() as u32, Semantics::Counter as u32);
+    assert_eq!(metric.unit(), unit.pmapi_repr);
+    assert_eq!(metric.shorthelp(), "short");
+    assert_eq!(metric.longhelp(), "long");
+    assert_eq!(metric.item(), 10);
+
+    let indom = Indom::new(&["a", "b"], "", "").unwrap();
+    let im = MetricBuilder::new("builder_instance_metric", 2i64)
+        .shorthelp("short")
+        .indom(&indom)
+        .item(11)
+        .build().unwrap();
+    assert_eq!(im.val("a"), Some(2));
+    assert_eq!(im.val("b"), Some(2));
+    assert_eq!(im.shorthelp(), "short");
+    assert_eq!(im.indom().instance_count(), 2);
+
+    assert!(MetricBuilder::new("builder_metric", 0).item(1 << 10).build().is_err());
+    let long_name: String = ::std::iter::repeat('a').take(STRING_BLOCK_LEN as usize).collect();
+    assert!(MetricBuilder::new(&long_name, 0).build().is_err());
+}
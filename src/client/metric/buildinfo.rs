@@ -0,0 +1,143 @@
+use super::*;
+use time;
+
+/// A set of metrics describing the build of a program, so that changes in
+/// other metrics can be correlated with deployments
+///
+/// Exports the `version`, `commit`, `profile`, `rustc` and `start_time`
+/// metrics under a given prefix, e.g., `myapp.build.version`. Each is a
+/// `Metric<String>` with `Semantics::Discrete`. Information that isn't
+/// known is exported as an empty string, which is reported as having no
+/// value available when the client has the `SENTINEL` flag set.
+///
+/// The `build_info!` macro fills in the information from the environment
+/// variables of the crate it's called in, at compile time.
+pub struct BuildInfo {
+    version: Metric<String>,
+    commit: Metric<String>,
+    profile: Metric<String>,
+    rustc: Metric<String>,
+    start_time: Metric<String>
+}
+
+/// Creates a `BuildInfo` under the given prefix, filled in at compile time
+/// from the environment of the calling crate
+///
+/// The version is taken from `CARGO_PKG_VERSION`, the git commit from
+/// `GIT_COMMIT`, and the rustc version from `RUSTC_VERSION`, the latter two
+/// usually being set by a build script. The profile is `debug` if debug
+/// assertions are enabled and `release` otherwise.
+#[macro_export]
+macro_rules! build_info {
+    ($prefix:expr) => {
+        $crate::client::metric::BuildInfo::new(
+            $prefix,
+            env!("CARGO_PKG_VERSION"),
+            option_env!("GIT_COMMIT").unwrap_or(""),
+            if cfg!(debug_assertions) { "debug" } else { "release" },
+            option_env!("RUSTC_VERSION").unwrap_or("")
+        )
+    }
+}
+
+impl BuildInfo {
+    /// Creates the build information metrics under the given prefix,
+    /// with the start time being the current time in RFC 3339 format
+    pub fn new(prefix: &str, version: &str, commit: &str, profile: &str,
+        rustc: &str) -> Result<Self, Error> {
+
+        let ns = Namespace::new(prefix)?;
+        let start_time = time::now_utc().rfc3339().to_string();
+
+        Ok(BuildInfo {
+            version: build_info_metric(&ns, "version", version, "Version of the program")?,
+            commit: build_info_metric(&ns, "commit", commit, "Source control commit the program was built from")?,
+            profile: build_info_metric(&ns, "profile", profile, "Profile the program was built with")?,
+            rustc: build_info_metric(&ns, "rustc", rustc, "Version of rustc the program was built with")?,
+            start_time: build_info_metric(&ns, "start_time", &start_time, "Time the program was started at")?
+        })
+    }
+
+    /// Version of the program
+    pub fn version(&self) -> String { self.version.val() }
+    /// Source control commit the program was built from
+    pub fn commit(&self) -> String { self.commit.val() }
+    /// Profile the program was built with
+    pub fn profile(&self) -> String { self.profile.val() }
+    /// Version of rustc the program was built with
+    pub fn rustc(&self) -> String { self.rustc.val() }
+    /// Time the program was started at, in RFC 3339 format
+    pub fn start_time(&self) -> String { self.start_time.val() }
+
+    fn metrics(&self) -> [&Metric<String>; 5] {
+        [&self.version, &self.commit, &self.profile, &self.rustc, &self.start_time]
+    }
+}
+
+fn build_info_metric(ns: &Namespace, name: &str, val: &str, help: &str)
+    -> Result<Metric<String>, Error> {
+    ns.metric(name, val.to_owned())
+        .semantics(Semantics::Discrete)
+        .shorthelp(help)
+        .build()
+}
+
+impl MMVWriter for BuildInfo {
+    private_impl!{}
+
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+        for m in self.metrics().iter() {
+            m.write(ws, c, mmv_ver)?;
+        }
+        Ok(())
+    }
+
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
+        for m in self.metrics().iter() {
+            m.register(ws, mmv_ver)?;
+        }
+        Ok(())
+    }
+
+    fn has_mmv2_string(&self) -> bool {
+        self.metrics().iter().any(|m| m.has_mmv2_string())
+    }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        Box::new(CompoundWriter(
+            self.metrics().iter().map(|m| m.share()).collect()
+        ))
+    }
+
+    fn id(&self) -> usize {
+        self.version.id()
+    }
+
+    fn name(&self) -> &str {
+        self.version.name()
+    }
+}
+
+#[test]
+pub fn test() {
+    use super::super::Client;
+    use super::super::super::mmv::dump;
+
+    let mut build_info = BuildInfo::new("buildinfo", "1.2.3", "abcdef", "release", "").unwrap();
+    assert_eq!(build_info.version(), "1.2.3");
+    assert_eq!(build_info.commit(), "abcdef");
+    assert_eq!(build_info.profile(), "release");
+    assert_eq!(build_info.rustc(), "");
+    assert!(!build_info.start_time().is_empty());
+    assert_eq!(build_info.name(), "buildinfo.version");
+
+    let client = Client::new("buildinfo_test").unwrap();
+    client.export(&mut [&mut build_info]).unwrap();
+    assert_eq!(dump(client.mmv_path()).unwrap().metric_blks().len(), 5);
+
+    let build_info = build_info!("buildinfo_macro").unwrap();
+    assert_eq!(build_info.version(), env!("CARGO_PKG_VERSION"));
+    assert_eq!(build_info.profile(), if cfg!(debug_assertions) { "debug" } else { "release" });
+
+    assert!(BuildInfo::new("1buildinfo", "", "", "", "").is_err());
+}
@@ -0,0 +1,158 @@
+use super::*;
+
+/// A metric whose value is pulled from a callback rather than set
+///
+/// Internally uses a `Metric<T>`, whose value is replaced with the result
+/// of the callback whenever the metric is sampled. A client samples the
+/// metrics it exports with `Client::sample`, or periodically on a
+/// background thread spawned with `Client::sample_every`.
+///
+/// The callback is called from whichever thread samples the metric,
+/// so it has to be `Send` and `Sync`.
+pub struct CallbackMetric<T> {
+    metric: Metric<T>,
+    callback: Arc<Fn() -> T + Send + Sync>
+}
+
+impl<T: MetricType + Clone> CallbackMetric<T> {
+    /// Creates a new callback metric, whose initial value is the
+    /// result of calling the callback
+    pub fn new<F>(name: &str, sem: Semantics, unit: Unit,
+        shorthelp_text: &str, longhelp_text: &str, callback: F) -> Result<Self, Error>
+        where F: Fn() -> T + Send + Sync + 'static {
+
+        let metric = Metric::new(
+            name,
+            callback(),
+            sem,
+            unit,
+            shorthelp_text,
+            longhelp_text
+        )?;
+
+        Ok(CallbackMetric {
+            metric: metric,
+            callback: Arc::new(callback)
+        })
+    }
+
+    /// Returns the value from when the metric was last sampled
+    pub fn val(&self) -> T {
+        self.metric.val()
+    }
+
+    /// Calls the callback and updates the metric with it's result
+    pub fn sample(&self) -> Result<(), Error> {
+        self.metric.set_val((self.callback)())
+    }
+
+    /// Sets the item ID of the metric, as with `Metric::set_item`
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        self.metric.set_item(item)
+    }
+}
+
+impl<T: MetricType + Clone> MMVWriter for CallbackMetric<T> {
+    private_impl!{}
+
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+        self.metric.write(ws, c, mmv_ver)
+    }
+
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
+        self.metric.register(ws, mmv_ver)
+    }
+
+    fn has_mmv2_string(&self) -> bool {
+        self.metric.has_mmv2_string()
+    }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        Box::new(CallbackMetric {
+            metric: self.metric.clone_shared(),
+            callback: self.callback.clone()
+        })
+    }
+
+    fn id(&self) -> usize {
+        self.metric.id()
+    }
+
+    fn name(&self) -> &str {
+        self.metric.name()
+    }
+
+    fn sample(&self) -> Result<(), Error> {
+        CallbackMetric::sample(self)
+    }
+}
+
+#[test]
+pub fn test() {
+    use super::super::Client;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    let pool_size = Arc::new(AtomicUsize::new(3));
+    let pool_size_clone = pool_size.clone();
+    let mut cm = CallbackMetric::new(
+        "callback_metric",
+        Semantics::Instant,
+        Unit::new().count(Count::One, 1).unwrap(),
+        "", "",
+        move || pool_size_clone.load(Ordering::SeqCst) as u64
+    ).unwrap();
+    assert_eq!(cm.val(), 3);
+
+    let client = Client::new("callback_metric_test").unwrap();
+    client.export(&mut [&mut cm]).unwrap();
+
+    pool_size.store(5, Ordering::SeqCst);
+    assert_eq!(cm.val(), 3);
+    client.sample().unwrap();
+    assert_eq!(cm.val(), 5);
+
+    pool_size.store(8, Ordering::SeqCst);
+    let handle = client.sample_every(Duration::from_millis(10));
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(cm.val(), 8);
+
+    drop(client);
+    handle.join().unwrap();
+}
+
+#[test]
+pub fn test_sample_error() {
+    use super::super::Client;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let len = Arc::new(AtomicUsize::new(1));
+    let len_clone = len.clone();
+    let mut label = CallbackMetric::new(
+        "callback_label", Semantics::Discrete, Unit::new(), "", "",
+        move || "a".repeat(len_clone.load(Ordering::SeqCst))
+    ).unwrap();
+
+    let count = Arc::new(AtomicUsize::new(1));
+    let count_clone = count.clone();
+    let mut cm = CallbackMetric::new(
+        "callback_count", Semantics::Instant, Unit::new(), "", "",
+        move || count_clone.load(Ordering::SeqCst) as u64
+    ).unwrap();
+
+    let client = Client::new("callback_sample_error_test").unwrap();
+    client.export(&mut [&mut label, &mut cm]).unwrap();
+
+    // a label too long for the MMV doesn't stop the count being sampled
+    len.store(1000, Ordering::SeqCst);
+    count.store(2, Ordering::SeqCst);
+    assert!(client.sample().is_err());
+    assert_eq!(label.val(), "a");
+    assert_eq!(cm.val(), 2);
+
+    count.store(3, Ordering::SeqCst);
+    let compound = CompoundWriter(vec![label.share(), cm.share()]);
+    assert!(compound.sample().is_err());
+    assert_eq!(cm.val(), 3);
+}
@@ -5,6 +5,12 @@ use super::*;
 ///
 /// Internally uses a `Metric<u64>` with `Semantics::Counter` and
 /// `Count::One` scale, and `1` count dimension
+///
+/// The counter saturates at `u64::MAX - 1`, since `u64::MAX` is the
+/// sentinel that reads as no value available.
+///
+/// The counter is updated atomically, so it can be shared between
+/// threads, e.g., with an `Arc<Counter>`.
 pub struct Counter {
     metric: Metric<u64>,
     init_val: u64
@@ -12,7 +18,7 @@ pub struct Counter {
 
 impl Counter {
     /// Creates a new counter metric with given initial value
-    pub fn new(name: &str, init_val: u64, shorthelp_text: &str, longhelp_text: &str) -> Result<Self, String> {
+    pub fn new(name: &str, init_val: u64, shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
         let metric = Metric::new(
             name,
             init_val,
@@ -34,37 +40,54 @@ impl Counter {
     }
 
     /// Increments the counter by the given value
-    pub fn inc(&mut self, increment: u64) -> io::Result<()> {
-        let val = self.metric.val();
-        self.metric.set_val(val + increment)
+    pub fn inc(&self, increment: u64) -> Result<(), Error> {
+        self.metric.update(|val| val.saturating_inc(increment))?;
+        Ok(())
     }
 
     /// Increments the counter by `+1`
-    pub fn up(&mut self) -> io::Result<()> {
+    pub fn up(&self) -> Result<(), Error> {
         self.inc(1)
     }
 
     /// Resets the counter to the initial value that was passed when
     /// creating it
-    pub fn reset(&mut self) -> io::Result<()> {
+    pub fn reset(&self) -> Result<(), Error> {
         self.metric.set_val(self.init_val)
     }
+
+    /// Sets the item ID of the counter, as with `Metric::set_item`
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        self.metric.set_item(item)
+    }
 }
 
 impl MMVWriter for Counter {
     private_impl!{}
 
-    fn write(&mut self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
         self.metric.write(ws, c, mmv_ver)
     }
 
-    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) {
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
         self.metric.register(ws, mmv_ver)
     }
 
     fn has_mmv2_string(&self) -> bool {
         self.metric.has_mmv2_string()
     }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        self.metric.share()
+    }
+
+    fn id(&self) -> usize {
+        self.metric.id()
+    }
+
+    fn name(&self) -> &str {
+        self.metric.name()
+    }
 }
 
 #[test]
@@ -85,4 +108,38 @@ pub fn test() {
 
     counter.reset().unwrap();
     assert_eq!(counter.val(), 1);
+
+    let counter = Counter::new("counter_bounds", ::std::u64::MAX - 2, "", "").unwrap();
+    counter.inc(5).unwrap();
+    assert_eq!(counter.val(), ::std::u64::MAX - 1);
+
+    let counter = Counter::new("counter_sentinel", ::std::u64::MAX, "", "").unwrap();
+    counter.up().unwrap();
+    assert_eq!(counter.val(), ::std::u64::MAX);
+}
+
+#[test]
+pub fn test_concurrent() {
+    use super::super::Client;
+    use std::sync::Arc;
+    use std::thread;
+
+    let mut counter = Counter::new("concurrent_counter", 0, "", "").unwrap();
+    Client::new("concurrent_counter_test").unwrap()
+        .export(&mut [&mut counter]).unwrap();
+
+    let counter = Arc::new(counter);
+    let threads: Vec<_> = (0..4).map(|_| {
+        let counter = counter.clone();
+        thread::spawn(move || {
+            for _ in 0..1000 {
+                counter.up().unwrap();
+            }
+        })
+    }).collect();
+
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(counter.val(), 4000);
 }
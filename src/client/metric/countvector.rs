@@ -1,28 +1,49 @@
 use std::collections::HashMap;
+use std::sync::RwLock;
 use super::*;
 
-/// A count vector for multiple strictly increasing integer values, in possibly
+/// A count vector for multiple strictly increasing values, in possibly
 /// varying increments
 ///
-/// Internally uses an `InstanceMetric<u64>` with `Semantics::Counter` and
-/// `Count::One` scale, and `1` count dimension
-pub struct CountVector {
-    im: InstanceMetric<u64>,
-    indom: Indom,
-    init_vals: HashMap<String, u64>
+/// Internally uses an `InstanceMetric<T>` with `Semantics::Counter` and
+/// `Count::One` scale, and `1` count dimension. The counts can be any
+/// numeric type, i.e., `i32`, `u32`, `i64`, `u64`, `f32` or `f64`. `new`
+/// creates a vector of `u64` counts, and `new_typed` one of any other type.
+///
+/// Integer counts saturate at the bounds of their type.
+///
+/// Counts are updated atomically, so the vector can be shared between
+/// threads, e.g., with an `Arc<CountVector>`.
+pub struct CountVector<T = u64> {
+    im: InstanceMetric<T>,
+    init_vals: RwLock<HashMap<String, T>>
 }
 
-impl CountVector {
+impl CountVector<u64> {
     /// Creates a new count vector with given instances and a single initial value
     pub fn new(name: &str, init_val: u64, instances: &[&str],
-        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, String> {
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
+        Self::new_typed(name, init_val, instances, shorthelp_text, longhelp_text)
+    }
+
+    /// Creates a new count vector with given pairs of an instance and it's initial value
+    pub fn new_with_initvals(name: &str, instances_and_initvals: &[(&str, u64)],
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
+        Self::new_typed_with_initvals(name, instances_and_initvals, shorthelp_text, longhelp_text)
+    }
+}
+
+impl<T: NumericType + Clone> CountVector<T> {
+    /// Creates a new count vector of any numeric type, as with `new`
+    pub fn new_typed(name: &str, init_val: T, instances: &[&str],
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
         
         let mut instances_and_initvals = Vec::new();
         for instance in instances {
             instances_and_initvals.push((*instance, init_val));
         }
 
-        Self::new_with_initvals(
+        Self::new_typed_with_initvals(
             name,
             &instances_and_initvals,
             shorthelp_text,
@@ -30,9 +51,9 @@ impl CountVector {
         )
     }
 
-    /// Creates a new count vector with given pairs of an instance and it's initial value
-    pub fn new_with_initvals(name: &str, instances_and_initvals: &[(&str, u64)],
-        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, String> {
+    /// Creates a new count vector of any numeric type, as with `new_with_initvals`
+    pub fn new_typed_with_initvals(name: &str, instances_and_initvals: &[(&str, T)],
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
         
         let mut instances = Vec::new();
         for &(instance, _) in instances_and_initvals.iter() {
@@ -40,15 +61,16 @@ impl CountVector {
         }
 
         let indom_helptext = format!("Instance domain for CounterVector '{}'", name);
-        let indom = Indom::new(
+        let indom = Indom::new_owned(
+            name,
             &instances,
             &indom_helptext, &indom_helptext
         )?;
         
-        let mut im = InstanceMetric::new(
+        let im = InstanceMetric::new(
             &indom,
             name,
-            0,
+            T::zero(),
             Semantics::Counter,
             Unit::new().count(Count::One, 1)?,
             shorthelp_text,
@@ -58,86 +80,132 @@ impl CountVector {
         let mut init_vals = HashMap::new();
         for &(instance, init_val) in instances_and_initvals.iter() {
             init_vals.insert(instance.to_owned(), init_val);
-            im.set_val(instance, init_val).unwrap().unwrap();
+            im.set_val(instance, init_val)?;
         }
 
         Ok(CountVector {
             im: im,
-            indom: indom,
-            init_vals: init_vals
+            init_vals: RwLock::new(init_vals)
         })
     }
 
     /// Returns the current count of the instance
-    pub fn val(&self, instance: &str) -> Option<u64> {
+    pub fn val(&self, instance: &str) -> Option<T> {
         self.im.val(instance)
     }
 
     /// Increments the count of the instance by the given value
     ///
-    /// The wrapping `Option` is `None` if the instance wasn't found
-    pub fn inc(&mut self, instance: &str, increment: u64) -> Option<io::Result<()>> {
-        self.im.val(instance).and_then(|val|
-            self.im.set_val(instance, val + increment)
-        )
+    /// The result is an `UnknownInstance` error if the instance wasn't found
+    pub fn inc(&self, instance: &str, increment: T) -> Result<(), Error> {
+        self.im.update(instance, |val| val.saturating_inc(increment))?;
+        Ok(())
     }
 
     /// Increments the count of the instance by `+1`
     ///
-    /// The wrapping `Option` is `None` if the instance wasn't found
-    pub fn up(&mut self, instance: &str) -> Option<io::Result<()>> {
-        self.inc(instance, 1)
+    /// The result is an `UnknownInstance` error if the instance wasn't found
+    pub fn up(&self, instance: &str) -> Result<(), Error> {
+        self.inc(instance, T::one())
     }
 
     /// Increments the count of all instances by the given value
-    pub fn inc_all(&mut self, increment: u64) -> io::Result<()> {
-        for instance in self.indom.instances_iter() {
-            let val = self.im.val(instance).unwrap();
-            self.im.set_val(instance, val + increment).unwrap()?;
-        }
-        Ok(())
+    pub fn inc_all(&self, increment: T) -> Result<(), Error> {
+        Ok(self.im.update_all(|val| val.saturating_inc(increment))?)
     }
 
     /// Increments the count of all instances by `+1`
-    pub fn up_all(&mut self) -> io::Result<()> {
-        self.inc_all(1)
+    pub fn up_all(&self) -> Result<(), Error> {
+        self.inc_all(T::one())
     }
 
     /// Resets the count of the instance to it's initial value that
     /// was passed when creating the vector
     ///
-    /// The wrapping `Option` is `None` if the instance wasn't found
-    pub fn reset(&mut self, instance: &str) -> Option<io::Result<()>> {
-        self.im.set_val(instance, *self.init_vals.get(instance).unwrap())
+    /// The result is an `UnknownInstance` error if the instance wasn't found
+    pub fn reset(&self, instance: &str) -> Result<(), Error> {
+        let init_val = self.read_init_vals().get(instance).cloned();
+        match init_val {
+            Some(init_val) => self.im.set_val(instance, init_val),
+            None => Err(Error::UnknownInstance(instance.to_owned()))
+        }
     }
 
     /// Resets the count of all instances to it's initial value that
     /// was passed when creating the vector
-    pub fn reset_all(&mut self) -> io::Result<()> {
-        for (instance, init_val) in self.init_vals.iter() {
-            self.im.set_val(instance, *init_val).unwrap()?;
+    pub fn reset_all(&self) -> Result<(), Error> {
+        for (instance, init_val) in self.read_init_vals().iter() {
+            self.im.set_val(instance, *init_val)?;
         }
         Ok(())
     }
 
+    /// Adds an instance with the given initial value, regenerating
+    /// the MMV if the vector is exported
+    ///
+    /// The result is a `DuplicateInstance` error if the instance already exists
+    pub fn add_instance(&self, instance: &str, init_val: T) -> Result<(), Error> {
+        let res = self.im.add_instance(instance, init_val);
+        if res.is_ok() {
+            self.write_init_vals().insert(instance.to_owned(), init_val);
+        }
+        res
+    }
+
+    /// Removes an instance, regenerating the MMV if the vector is exported
+    ///
+    /// The result is an `UnknownInstance` error if the instance wasn't found
+    pub fn remove_instance(&self, instance: &str) -> Result<(), Error> {
+        let res = self.im.remove_instance(instance);
+        if res.is_ok() {
+            self.write_init_vals().remove(instance);
+        }
+        res
+    }
+
     /// Internally created instance domain
-    pub fn indom(&self) -> &Indom { &self.indom }
+    pub fn indom(&self) -> Indom { self.im.indom() }
+
+    fn read_init_vals(&self) -> RwLockReadGuard<HashMap<String, T>> {
+        self.init_vals.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write_init_vals(&self) -> RwLockWriteGuard<HashMap<String, T>> {
+        self.init_vals.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sets the item ID of the count vector, as with `Metric::set_item`
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        self.im.set_item(item)
+    }
 }
 
-impl MMVWriter for CountVector {
+impl<T: NumericType + Clone> MMVWriter for CountVector<T> {
     private_impl!{}
 
-    fn write(&mut self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
         self.im.write(ws, c, mmv_ver)
     }
 
-    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) {
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
         self.im.register(ws, mmv_ver)
     }
 
     fn has_mmv2_string(&self) -> bool {
         self.im.has_mmv2_string()
     }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        self.im.share()
+    }
+
+    fn id(&self) -> usize {
+        self.im.id()
+    }
+
+    fn name(&self) -> &str {
+        self.im.name()
+    }
 }
 
 #[test]
@@ -158,10 +226,10 @@ pub fn test() {
     Client::new("count_vector_test").unwrap()
         .export(&mut [&mut cv]).unwrap();
     
-    cv.up("b").unwrap().unwrap();
+    cv.up("b").unwrap();
     assert_eq!(cv.val("b").unwrap(), 2);
 
-    cv.inc("c", 3).unwrap().unwrap();
+    cv.inc("c", 3).unwrap();
     assert_eq!(cv.val("c").unwrap(), 4);
 
     cv.inc_all(2).unwrap();
@@ -174,7 +242,7 @@ pub fn test() {
     assert_eq!(cv.val("b").unwrap(), 5);
     assert_eq!(cv.val("c").unwrap(), 7);
 
-    cv.reset("b").unwrap().unwrap();
+    cv.reset("b").unwrap();
     assert_eq!(cv.val("b").unwrap(), 1);
 
     cv.reset_all().unwrap();
@@ -205,7 +273,7 @@ pub fn test_multiple_initvals() {
     assert_eq!(cv.val("b").unwrap(), 3);
     assert_eq!(cv.val("c").unwrap(), 4);
 
-    cv.reset("b").unwrap().unwrap();
+    cv.reset("b").unwrap();
     assert_eq!(cv.val("b").unwrap(), 2);
 
     cv.reset_all().unwrap();
@@ -213,3 +281,49 @@ pub fn test_multiple_initvals() {
     assert_eq!(cv.val("b").unwrap(), 2);
     assert_eq!(cv.val("c").unwrap(), 3);
 }
+
+#[test]
+pub fn test_add_remove_instances() {
+    use super::super::Client;
+    use super::super::super::mmv::{dump, MTCode};
+
+    let mut cv = CountVector::new("count_vector_add_remove", 1, &["a", "b"], "", "").unwrap();
+    let client = Client::new("count_vector_add_remove_test").unwrap();
+    client.export(&mut [&mut cv]).unwrap();
+
+    // counts from integer literals are still u64
+    let mmv = dump(client.mmv_path()).unwrap();
+    for metric_blk in mmv.metric_blks().values() {
+        assert_eq!(metric_blk.typ(), MTCode::U64 as u32);
+    }
+
+    cv.inc("a", 4).unwrap();
+
+    cv.add_instance("c", 10).unwrap();
+    assert!(cv.add_instance("c", 10).is_err());
+    assert!(cv.indom().has_instance("c"));
+    assert_eq!(cv.val("a").unwrap(), 5);
+    assert_eq!(cv.val("c").unwrap(), 10);
+
+    cv.up_all().unwrap();
+    assert_eq!(cv.val("c").unwrap(), 11);
+    cv.reset("c").unwrap();
+    assert_eq!(cv.val("c").unwrap(), 10);
+
+    cv.remove_instance("a").unwrap();
+    assert!(cv.remove_instance("a").is_err());
+    assert!(cv.val("a").is_none());
+    assert!(cv.reset("a").is_err());
+    assert_eq!(cv.indom().instance_count(), 2);
+}
+
+#[test]
+pub fn test_float_counts() {
+    let cv = CountVector::new_typed("count_vector_float", 0.5f32, &["a"], "", "").unwrap();
+    cv.up("a").unwrap();
+    cv.inc_all(0.25).unwrap();
+    assert_eq!(cv.val("a").unwrap(), 1.75);
+
+    cv.reset_all().unwrap();
+    assert_eq!(cv.val("a").unwrap(), 0.5);
+}
@@ -0,0 +1,351 @@
+use std::fmt::Display;
+use super::*;
+
+/// A family of metrics with the same type, semantics and unit, that're
+/// addressed by values of a fixed set of labels
+///
+/// Internally uses an `InstanceMetric<T>`, with an instance for every
+/// combination of label values that's used. Label values are mapped to an
+/// instance name that joins every label and it's value in the order the
+/// labels were declared, e.g., the values `("GET", 200)` for the labels
+/// `method` and `status` map to the instance `method=GET,status=200`.
+///
+/// Instances are added the first time their label values are used,
+/// regenerating the MMV if the family is exported, and can be added
+/// ahead of time with `add`.
+///
+/// Values are updated atomically, so the family can be shared between
+/// threads, e.g., with an `Arc<Family>`.
+pub struct Family<T = u64> {
+    im: InstanceMetric<T>,
+    labels: Vec<String>,
+    init_val: T
+}
+
+/// Values of the labels of a `Family`
+///
+/// Implemented for slices of strings, and for tuples of up to six
+/// values that can be displayed.
+pub trait LabelValues {
+    /// Returns the label values as strings, in order
+    fn label_values(&self) -> Vec<String>;
+}
+
+impl<'a, S: AsRef<str>> LabelValues for &'a [S] {
+    fn label_values(&self) -> Vec<String> {
+        self.iter().map(|val| val.as_ref().to_owned()).collect()
+    }
+}
+
+macro_rules! impl_label_values_for_tuple (
+    ($($typ:ident: $idx:tt),+) => (
+        impl<$($typ: Display),+> LabelValues for ($($typ,)+) {
+            fn label_values(&self) -> Vec<String> {
+                vec![$(self.$idx.to_string()),+]
+            }
+        }
+    )
+);
+
+impl_label_values_for_tuple!(A: 0);
+impl_label_values_for_tuple!(A: 0, B: 1);
+impl_label_values_for_tuple!(A: 0, B: 1, C: 2);
+impl_label_values_for_tuple!(A: 0, B: 1, C: 2, D: 3);
+impl_label_values_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_label_values_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+
+// labels and their values can't contain the separators of instance names
+fn check_label(label: &str) -> Result<(), Error> {
+    if label.contains(',') || label.contains('=') {
+        return Err(Error::InvalidLabel(label.to_owned()));
+    }
+    Ok(())
+}
+
+impl<T: MetricType + Clone> Family<T> {
+    /// Creates a new family with given label names, whose instances
+    /// start with the given initial value
+    ///
+    /// The result is an `InvalidLabel` error if a label name is empty
+    /// or contains a `,` or `=`.
+    pub fn new(labels: &[&str], name: &str, init_val: T, sem: Semantics,
+        unit: Unit, shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
+
+        for label in labels {
+            if label.is_empty() {
+                return Err(Error::InvalidLabel(String::new()));
+            }
+            check_label(label)?;
+        }
+
+        let indom_helptext = format!("Instance domain for Family '{}'", name);
+        let indom = Indom::new_owned(name, &[], &indom_helptext, &indom_helptext)?;
+
+        let im = InstanceMetric::new(
+            &indom,
+            name,
+            init_val.clone(),
+            sem,
+            unit,
+            shorthelp_text,
+            longhelp_text
+        )?;
+
+        Ok(Family {
+            im: im,
+            labels: labels.iter().map(|label| (*label).to_owned()).collect(),
+            init_val: init_val
+        })
+    }
+
+    /// Returns the instance name the label values map to
+    ///
+    /// The result is a `LabelCountMismatch` error if the number of values
+    /// differs from the number of labels, or an `InvalidLabel` error if a
+    /// value contains a `,` or `=`.
+    pub fn instance<L: LabelValues>(&self, values: L) -> Result<String, Error> {
+        let values = values.label_values();
+        if values.len() != self.labels.len() {
+            return Err(Error::LabelCountMismatch {
+                expected: self.labels.len(),
+                found: values.len()
+            });
+        }
+
+        let mut instance = String::new();
+        for (label, val) in self.labels.iter().zip(values.iter()) {
+            check_label(val)?;
+            if !instance.is_empty() {
+                instance.push(',');
+            }
+            instance.push_str(label);
+            instance.push('=');
+            instance.push_str(val);
+        }
+        Ok(instance)
+    }
+
+    // returns the instance of the label values, adding it if
+    // it doesn't exist yet
+    fn instance_or_add<L: LabelValues>(&self, values: L) -> Result<String, Error> {
+        let instance = self.instance(values)?;
+        if !self.im.has_instance(&instance) {
+            match self.im.add_instance(&instance, self.init_val.clone()) {
+                // added by another thread in the meantime
+                Ok(()) | Err(Error::DuplicateInstance(_)) => {},
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(instance)
+    }
+
+    /// Adds the instance of the label values ahead of it's first use,
+    /// if it doesn't exist yet
+    pub fn add<L: LabelValues>(&self, values: L) -> Result<(), Error> {
+        self.instance_or_add(values)?;
+        Ok(())
+    }
+
+    /// Removes the instance of the label values, regenerating the MMV
+    /// if the family is exported
+    ///
+    /// The result is an `UnknownInstance` error if the instance wasn't found
+    pub fn remove<L: LabelValues>(&self, values: L) -> Result<(), Error> {
+        self.im.remove_instance(&self.instance(values)?)
+    }
+
+    /// Returns the value of the label values, or `None` if
+    /// they haven't been used yet
+    pub fn val<L: LabelValues>(&self, values: L) -> Option<T> {
+        self.instance(values).ok().and_then(|instance| self.im.val(&instance))
+    }
+
+    /// Sets the value of the label values
+    pub fn set<L: LabelValues>(&self, values: L, val: T) -> Result<(), Error> {
+        self.im.set_val(&self.instance_or_add(values)?, val)
+    }
+
+    /// Replaces the value of the label values with `f` applied to it,
+    /// and returns the new value
+    pub fn update<L: LabelValues, F: FnMut(T) -> T>(&self, values: L, f: F) -> Result<T, Error> {
+        self.im.update(&self.instance_or_add(values)?, f)
+    }
+
+    /// Clears the value of the label values, as with `InstanceMetric::clear`
+    pub fn clear<L: LabelValues>(&self, values: L) -> Result<(), Error> {
+        self.im.clear(&self.instance_or_add(values)?)
+    }
+
+    /// Names of the labels
+    pub fn labels(&self) -> &[String] { &self.labels }
+
+    /// Number of label values that're in use
+    pub fn len(&self) -> usize { self.im.instance_count() as usize }
+
+    /// Checks if no label values are in use
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Current instance domain
+    pub fn indom(&self) -> Indom { self.im.indom() }
+
+    /// Sets the item ID of the family, as with `Metric::set_item`
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        self.im.set_item(item)
+    }
+}
+
+impl<T: NumericType + Clone> Family<T> {
+    /// Increments the value of the label values by the given value
+    pub fn inc<L: LabelValues>(&self, values: L, increment: T) -> Result<(), Error> {
+        self.update(values, |val| val.saturating_inc(increment))?;
+        Ok(())
+    }
+
+    /// Increments the value of the label values by `+1`
+    pub fn up<L: LabelValues>(&self, values: L) -> Result<(), Error> {
+        self.inc(values, T::one())
+    }
+
+    /// Decrements the value of the label values by the given value
+    pub fn dec<L: LabelValues>(&self, values: L, decrement: T) -> Result<(), Error> {
+        self.update(values, |val| val.saturating_dec(decrement))?;
+        Ok(())
+    }
+}
+
+impl<T: MetricType + Clone> MMVWriter for Family<T> {
+    private_impl!{}
+
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+        self.im.write(ws, c, mmv_ver)
+    }
+
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
+        self.im.register(ws, mmv_ver)
+    }
+
+    fn has_mmv2_string(&self) -> bool {
+        self.im.has_mmv2_string()
+    }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        self.im.share()
+    }
+
+    fn id(&self) -> usize {
+        self.im.id()
+    }
+
+    fn name(&self) -> &str {
+        self.im.name()
+    }
+}
+
+#[test]
+pub fn test() {
+    use super::super::Client;
+    use super::super::super::mmv::dump;
+
+    let mut requests = Family::new(
+        &["method", "status"],
+        "family_requests",
+        0u64,
+        Semantics::Counter,
+        Unit::new().count(Count::One, 1).unwrap(),
+        "", ""
+    ).unwrap();
+    requests.add(("GET", 200)).unwrap();
+    assert_eq!(requests.len(), 1);
+
+    let client = Client::new("family_test").unwrap();
+    client.export(&mut [&mut requests]).unwrap();
+
+    assert_eq!(requests.instance(("GET", 200)).unwrap(), "method=GET,status=200");
+    assert_eq!(requests.instance(&["GET", "200"][..]).unwrap(), "method=GET,status=200");
+
+    requests.up(("GET", 200)).unwrap();
+    requests.inc(("POST", 201), 2).unwrap();
+    requests.up(&["POST", "201"][..]).unwrap();
+    assert_eq!(requests.val(("GET", 200)), Some(1));
+    assert_eq!(requests.val(("POST", 201)), Some(3));
+    assert_eq!(requests.val(("PUT", 200)), None);
+    assert!(requests.indom().has_instance("method=POST,status=201"));
+    assert_eq!(dump(client.mmv_path()).unwrap().instance_blks().len(), 2);
+
+    match requests.up(("GET",)) {
+        Err(Error::LabelCountMismatch { expected: 2, found: 1 }) => {},
+        _ => panic!("expected a LabelCountMismatch error")
+    }
+    assert!(requests.up(("GET,HEAD", 200)).is_err());
+    assert!(requests.up(("GET", "=")).is_err());
+
+    requests.remove(("GET", 200)).unwrap();
+    assert!(requests.remove(("GET", 200)).is_err());
+    assert_eq!(requests.len(), 1);
+
+    requests.inc(("POST", 201), ::std::u64::MAX).unwrap();
+    assert_eq!(requests.val(("POST", 201)), Some(::std::u64::MAX - 1));
+    requests.dec(("PUT", 200), 1).unwrap();
+    assert_eq!(requests.val(("PUT", 200)), Some(0));
+
+    assert!(Family::new(&["a,b"], "family_invalid", 0, Semantics::Counter, Unit::new(), "", "").is_err());
+    assert!(Family::new(&[""], "family_invalid", 0, Semantics::Counter, Unit::new(), "", "").is_err());
+}
+
+#[test]
+pub fn test_empty() {
+    use super::super::Client;
+    use super::super::super::mmv::dump;
+
+    let mut family = Family::new(&["method"], "family_empty", 0u64,
+        Semantics::Counter, Unit::new(), "", "").unwrap();
+
+    let client = Client::new("family_empty_test").unwrap();
+    client.export(&mut [&mut family]).unwrap();
+
+    let mmv = dump(client.mmv_path()).unwrap();
+    // every section but the instance one
+    assert_eq!(mmv.header().toc_count(), 4);
+    assert!(mmv.instance_toc().is_none());
+    assert!(mmv.value_blks().is_empty());
+    assert_eq!(mmv.indom_blks().len(), 1);
+    let indom = mmv.indom_blks().values().next().unwrap();
+    assert_eq!(indom.instances(), 0);
+    assert!(indom.instances_offset().is_none());
+
+    // instances are exported once added, and the indom is empty again
+    // after they're removed
+    family.up(("GET",)).unwrap();
+    assert_eq!(dump(client.mmv_path()).unwrap().instance_blks().len(), 1);
+    family.remove(("GET",)).unwrap();
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert!(mmv.instance_toc().is_none());
+    assert!(mmv.instance_blks().is_empty());
+}
+
+#[test]
+pub fn test_shared_labels() {
+    use super::super::Client;
+    use super::super::super::mmv::{dump, Value};
+
+    let mut requests = Family::new(&["method", "status"], "family_shared_requests", 0u64,
+        Semantics::Counter, Unit::new(), "", "").unwrap();
+    let mut bytes = Family::new(&["method", "status"], "family_shared_bytes", 0u64,
+        Semantics::Counter, Unit::new(), "", "").unwrap();
+
+    let client = Client::new("family_shared_labels_test").unwrap();
+    client.export(&mut [&mut requests, &mut bytes]).unwrap();
+
+    for &(method, status, n) in &[("GET", 200, 1), ("POST", 201, 2), ("PUT", 204, 3)] {
+        requests.inc((method, status), n).unwrap();
+        bytes.inc((method, status), 100 * n).unwrap();
+    }
+
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert_eq!(mmv.indom_blks().len(), 2);
+    for &(inst, n) in &[("method=GET,status=200", 1), ("method=POST,status=201", 2),
+        ("method=PUT,status=204", 3)] {
+        assert_eq!(mmv.get("family_shared_requests", Some(inst)), Some(Value::U64(n)));
+        assert_eq!(mmv.get("family_shared_bytes", Some(inst)), Some(Value::U64(100 * n)));
+    }
+}
@@ -5,6 +5,9 @@ use super::*;
 ///
 /// Internally uses a `Metric<f64>` with `Semantics::Instant`,
 /// `Count::One` scale, and `1` count dimension
+///
+/// The gauge is updated atomically, so it can be shared between
+/// threads, e.g., with an `Arc<Gauge>`.
 pub struct Gauge {
     metric: Metric<f64>,
     init_val: f64
@@ -12,7 +15,7 @@ pub struct Gauge {
 
 impl Gauge {
     /// Creates a new gauge metric with given initial value
-    pub fn new(name: &str, init_val: f64, shorthelp_text: &str, longhelp_text: &str) -> Result<Self, String> {
+    pub fn new(name: &str, init_val: f64, shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
         let metric = Metric::new(
             name,
             init_val,
@@ -34,43 +37,76 @@ impl Gauge {
     }
 
     /// Sets the value of the gauge
-    pub fn set(&mut self, val: f64) -> io::Result<()> {
+    pub fn set(&self, val: f64) -> Result<(), Error> {
         self.metric.set_val(val)
     }
 
     /// Increments the gauge by the given value
-    pub fn inc(&mut self, increment: f64) -> io::Result<()> {
-        let val = self.metric.val();
-        self.metric.set_val(val + increment)
+    pub fn inc(&self, increment: f64) -> Result<(), Error> {
+        self.metric.update(|val| val.saturating_inc(increment))?;
+        Ok(())
     }
 
     /// Decrements the gauge by the given value
-    pub fn dec(&mut self, decrement: f64) -> io::Result<()> {
-        let val = self.metric.val();
-        self.metric.set_val(val - decrement)
+    pub fn dec(&self, decrement: f64) -> Result<(), Error> {
+        self.metric.update(|val| val.saturating_dec(decrement))?;
+        Ok(())
     }
 
     /// Resets the gauge to the initial value that was passed when
     /// creating it
-    pub fn reset(&mut self) -> io::Result<()> {
+    pub fn reset(&self) -> Result<(), Error> {
         self.metric.set_val(self.init_val)
     }
+
+    /// Clears the gauge, so that it's reported as having no value
+    /// available until it's set or reset again. Incrementing or
+    /// decrementing a cleared gauge leaves it cleared, as with every
+    /// numeric metric; see `Metric::clear`.
+    ///
+    /// The client has to have the `SENTINEL` flag set for PCP
+    /// to interpret the cleared value.
+    pub fn clear(&self) -> Result<(), Error> {
+        self.metric.clear()
+    }
+
+    /// Checks if the gauge has a value available
+    pub fn is_available(&self) -> bool {
+        self.metric.is_available()
+    }
+
+    /// Sets the item ID of the gauge, as with `Metric::set_item`
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        self.metric.set_item(item)
+    }
 }
 
 impl MMVWriter for Gauge {
     private_impl!{}
 
-    fn write(&mut self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
         self.metric.write(ws, c, mmv_ver)
     }
 
-    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) {
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
         self.metric.register(ws, mmv_ver)
     }
 
     fn has_mmv2_string(&self) -> bool {
         self.metric.has_mmv2_string()
     }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        self.metric.share()
+    }
+
+    fn id(&self) -> usize {
+        self.metric.id()
+    }
+
+    fn name(&self) -> &str {
+        self.metric.name()
+    }
 }
 
 #[test]
@@ -94,4 +130,12 @@ pub fn test() {
 
     gauge.reset().unwrap();
     assert_eq!(gauge.val(), 1.5);
+
+    gauge.clear().unwrap();
+    assert!(!gauge.is_available());
+    gauge.inc(1.0).unwrap();
+    assert!(!gauge.is_available());
+
+    gauge.set(2.0).unwrap();
+    assert!(gauge.is_available());
 }
@@ -1,116 +1,232 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
 use super::*;
 
-/// A gauge vector for multiple floating point values with helper methods
+/// A gauge vector for multiple values with helper methods
 /// for incrementing and decrementing their value
 ///
-/// Internally uses an `InstanceMetric<f64>` with `Semantics::Instant` and
-/// `Count::One` scale, and `1` count dimension
-pub struct GaugeVector {
-    im: InstanceMetric<f64>,
-    indom: Indom,
-    init_val: f64
+/// Internally uses an `InstanceMetric<T>` with `Semantics::Instant` and
+/// `Count::One` scale, and `1` count dimension. The gauges can be any
+/// numeric type, i.e., `i32`, `u32`, `i64`, `u64`, `f32` or `f64`. `new`
+/// creates a vector of `f64` gauges, and `new_typed` one of any other type.
+///
+/// Integer gauges saturate at the bounds of their type, and cleared
+/// gauges stay cleared when incremented or decremented.
+///
+/// Gauges are updated atomically, so the vector can be shared between
+/// threads, e.g., with an `Arc<GaugeVector>`.
+pub struct GaugeVector<T = f64> {
+    im: InstanceMetric<T>,
+    init_vals: RwLock<HashMap<String, T>>
 }
 
-impl GaugeVector {
+impl GaugeVector<f64> {
     /// Creates a new gauge vector with given initial value and instances
     pub fn new(name: &str, init_val: f64, instances: &[&str],
-        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, String> {
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
+        Self::new_typed(name, init_val, instances, shorthelp_text, longhelp_text)
+    }
+
+    /// Creates a new gauge vector with given pairs of an instance and it's initial value
+    pub fn new_with_initvals(name: &str, instances_and_initvals: &[(&str, f64)],
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
+        Self::new_typed_with_initvals(name, instances_and_initvals, shorthelp_text, longhelp_text)
+    }
+}
+
+impl<T: NumericType + Clone> GaugeVector<T> {
+    /// Creates a new gauge vector of any numeric type, as with `new`
+    pub fn new_typed(name: &str, init_val: T, instances: &[&str],
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
+
+        let mut instances_and_initvals = Vec::new();
+        for instance in instances {
+            instances_and_initvals.push((*instance, init_val));
+        }
+
+        Self::new_typed_with_initvals(
+            name,
+            &instances_and_initvals,
+            shorthelp_text,
+            longhelp_text
+        )
+    }
+
+    /// Creates a new gauge vector of any numeric type, as with `new_with_initvals`
+    pub fn new_typed_with_initvals(name: &str, instances_and_initvals: &[(&str, T)],
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
+
+        let mut instances = Vec::new();
+        for &(instance, _) in instances_and_initvals.iter() {
+            instances.push(instance);
+        }
         
         let indom_helptext = format!("Instance domain for GaugeVector '{}'", name);
-        let indom = Indom::new(instances, &indom_helptext, &indom_helptext)?;
+        let indom = Indom::new_owned(name, &instances, &indom_helptext, &indom_helptext)?;
         
         let im = InstanceMetric::new(
             &indom,
             name,
-            init_val,
-            Semantics::Counter,
+            T::zero(),
+            Semantics::Instant,
             Unit::new().count(Count::One, 1)?,
             shorthelp_text,
             longhelp_text
         )?;
 
+        let mut init_vals = HashMap::new();
+        for &(instance, init_val) in instances_and_initvals.iter() {
+            init_vals.insert(instance.to_owned(), init_val);
+            im.set_val(instance, init_val)?;
+        }
+
         Ok(GaugeVector {
             im: im,
-            indom: indom,
-            init_val: init_val
+            init_vals: RwLock::new(init_vals)
         })
     }
 
     /// Returns the current gauge of the instance
-    pub fn val(&self, instance: &str) -> Option<f64> {
+    pub fn val(&self, instance: &str) -> Option<T> {
         self.im.val(instance)
     }
 
     /// Sets the gauge of the instance
-    pub fn set(&mut self, instance: &str, val: f64) -> Option<io::Result<()>> {
+    ///
+    /// The result is an `UnknownInstance` error if the instance wasn't found
+    pub fn set(&self, instance: &str, val: T) -> Result<(), Error> {
         self.im.set_val(instance, val)
     }
 
     /// Increments the gauge of the instance by the given value
     ///
-    /// The wrapping `Option` is `None` if the instance wasn't found
-    pub fn inc(&mut self, instance: &str, increment: f64) -> Option<io::Result<()>> {
-        self.im.val(instance).and_then(|val|
-            self.im.set_val(instance, val + increment)
-        )
+    /// The result is an `UnknownInstance` error if the instance wasn't found
+    pub fn inc(&self, instance: &str, increment: T) -> Result<(), Error> {
+        self.im.update(instance, |val| val.saturating_inc(increment))?;
+        Ok(())
     }
 
     /// Decrements the gauge of the instance by the given value
     ///
-    /// The wrapping `Option` is `None` if the instance wasn't found
-    pub fn dec(&mut self, instance: &str, decrement: f64) -> Option<io::Result<()>> {
-        self.inc(instance, -decrement)
+    /// The result is an `UnknownInstance` error if the instance wasn't found
+    pub fn dec(&self, instance: &str, decrement: T) -> Result<(), Error> {
+        self.im.update(instance, |val| val.saturating_dec(decrement))?;
+        Ok(())
     }
 
     /// Increments the gauge of all instances by the given value
-    pub fn inc_all(&mut self, increment: f64) -> io::Result<()> {
-        for instance in self.indom.instances_iter() {
-            let val = self.im.val(instance).unwrap();
-            self.im.set_val(instance, val + increment).unwrap()?;
-        }
-        Ok(())
+    pub fn inc_all(&self, increment: T) -> Result<(), Error> {
+        Ok(self.im.update_all(|val| val.saturating_inc(increment))?)
     }
 
     /// Decrements the gauge of all instances by the given value
-    pub fn dec_all(&mut self, decrement: f64) -> io::Result<()> {
-        self.inc_all(-decrement)
+    pub fn dec_all(&self, decrement: T) -> Result<(), Error> {
+        Ok(self.im.update_all(|val| val.saturating_dec(decrement))?)
     }
 
-    /// Resets the gauge of the instance to the initial value that
+    /// Resets the gauge of the instance to it's initial value that
     /// was passed when creating the vector
     ///
-    /// The wrapping `Option` is `None` if the instance wasn't found
-    pub fn reset(&mut self, instance: &str) -> Option<io::Result<()>> {
-        self.im.set_val(instance, self.init_val)
+    /// The result is an `UnknownInstance` error if the instance wasn't found
+    pub fn reset(&self, instance: &str) -> Result<(), Error> {
+        let init_val = self.read_init_vals().get(instance).cloned();
+        match init_val {
+            Some(init_val) => self.im.set_val(instance, init_val),
+            None => Err(Error::UnknownInstance(instance.to_owned()))
+        }
     }
 
-    /// Resets the gauge of all instances to the initial value that
+    /// Resets the gauge of all instances to it's initial value that
     /// was passed when creating the vector
-    pub fn reset_all(&mut self) -> io::Result<()> {
-        for instance in self.indom.instances_iter() {
-            self.im.set_val(instance, self.init_val).unwrap()?;
+    pub fn reset_all(&self) -> Result<(), Error> {
+        for (instance, init_val) in self.read_init_vals().iter() {
+            self.im.set_val(instance, *init_val)?;
         }
         Ok(())
     }
 
+    /// Clears the gauge of the instance, so that it's reported as having
+    /// no value available until it's set or reset again
+    ///
+    /// The client has to have the `SENTINEL` flag set for PCP
+    /// to interpret the cleared value.
+    ///
+    /// The result is an `UnknownInstance` error if the instance wasn't found
+    pub fn clear(&self, instance: &str) -> Result<(), Error> {
+        self.im.clear(instance)
+    }
+
+    /// Checks if the gauge of the instance has a value available
+    pub fn is_available(&self, instance: &str) -> bool {
+        self.im.is_available(instance)
+    }
+
+    /// Adds an instance with the given initial value, regenerating
+    /// the MMV if the vector is exported
+    ///
+    /// The result is a `DuplicateInstance` error if the instance already exists
+    pub fn add_instance(&self, instance: &str, init_val: T) -> Result<(), Error> {
+        let res = self.im.add_instance(instance, init_val);
+        if res.is_ok() {
+            self.write_init_vals().insert(instance.to_owned(), init_val);
+        }
+        res
+    }
+
+    /// Removes an instance, regenerating the MMV if the vector is exported
+    ///
+    /// The result is an `UnknownInstance` error if the instance wasn't found
+    pub fn remove_instance(&self, instance: &str) -> Result<(), Error> {
+        let res = self.im.remove_instance(instance);
+        if res.is_ok() {
+            self.write_init_vals().remove(instance);
+        }
+        res
+    }
+
     /// Internally created instance domain
-    pub fn indom(&self) -> &Indom { &self.indom }
+    pub fn indom(&self) -> Indom { self.im.indom() }
+
+    fn read_init_vals(&self) -> RwLockReadGuard<HashMap<String, T>> {
+        self.init_vals.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write_init_vals(&self) -> RwLockWriteGuard<HashMap<String, T>> {
+        self.init_vals.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sets the item ID of the gauge vector, as with `Metric::set_item`
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        self.im.set_item(item)
+    }
 }
 
-impl MMVWriter for GaugeVector {
+impl<T: NumericType + Clone> MMVWriter for GaugeVector<T> {
     private_impl!{}
 
-    fn write(&mut self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
         self.im.write(ws, c, mmv_ver)
     }
 
-    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) {
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
         self.im.register(ws, mmv_ver)
     }
 
     fn has_mmv2_string(&self) -> bool {
         self.im.has_mmv2_string()
     }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        self.im.share()
+    }
+
+    fn id(&self) -> usize {
+        self.im.id()
+    }
+
+    fn name(&self) -> &str {
+        self.im.name()
+    }
 }
 
 #[test]
@@ -130,13 +246,13 @@ pub fn test() {
     Client::new("count_vector_test").unwrap()
         .export(&mut [&mut gv]).unwrap();
     
-    gv.set("a", 2.5).unwrap().unwrap();
+    gv.set("a", 2.5).unwrap();
     assert_eq!(gv.val("a").unwrap(), 2.5);
 
-    gv.inc("b", 1.5).unwrap().unwrap();
+    gv.inc("b", 1.5).unwrap();
     assert_eq!(gv.val("b").unwrap(), 3.0);
 
-    gv.dec("c", 1.5).unwrap().unwrap();
+    gv.dec("c", 1.5).unwrap();
     assert_eq!(gv.val("c").unwrap(), 0.0);
 
     gv.inc_all(2.0).unwrap();
@@ -149,7 +265,7 @@ pub fn test() {
     assert_eq!(gv.val("b").unwrap(), 4.5);
     assert_eq!(gv.val("c").unwrap(), 1.5);
 
-    gv.reset("b").unwrap().unwrap();
+    gv.reset("b").unwrap();
     assert_eq!(gv.val("b").unwrap(), 1.5);
 
     gv.reset_all().unwrap();
@@ -157,3 +273,69 @@ pub fn test() {
     assert_eq!(gv.val("b").unwrap(), 1.5);
     assert_eq!(gv.val("c").unwrap(), 1.5);
 }
+
+#[test]
+pub fn test_integer_gauges() {
+    use super::super::Client;
+    use super::super::super::mmv::dump;
+
+    let mut gv = GaugeVector::new_typed_with_initvals(
+        "gauge_vector_integer",
+        &[("a", 1u32), ("b", 2)],
+        "", "").unwrap();
+
+    let client = Client::new("gauge_vector_integer_test").unwrap();
+    client.export(&mut [&mut gv]).unwrap();
+
+    let mmv = dump(client.mmv_path()).unwrap();
+    for metric_blk in mmv.metric_blks().values() {
+        assert_eq!(metric_blk.sem(), Semantics::Instant as u32);
+    }
+
+    gv.inc_all(3).unwrap();
+    gv.dec("a", 2).unwrap();
+    assert_eq!(gv.val("a").unwrap(), 2);
+    assert_eq!(gv.val("b").unwrap(), 5);
+
+    gv.add_instance("c", 10).unwrap();
+    assert!(gv.add_instance("c", 10).is_err());
+    gv.inc("c", 1).unwrap();
+    assert_eq!(gv.val("c").unwrap(), 11);
+
+    gv.reset_all().unwrap();
+    assert_eq!(gv.val("a").unwrap(), 1);
+    assert_eq!(gv.val("b").unwrap(), 2);
+    assert_eq!(gv.val("c").unwrap(), 10);
+
+    gv.remove_instance("c").unwrap();
+    assert!(gv.reset("c").is_err());
+}
+
+#[test]
+pub fn test_integer_bounds() {
+    let gv = GaugeVector::new_typed("gauge_vector_bounds", 0u32, &["a"], "", "").unwrap();
+
+    // saturates at zero
+    gv.dec("a", 1).unwrap();
+    assert_eq!(gv.val("a").unwrap(), 0);
+
+    // saturates short of the sentinel
+    gv.set("a", ::std::u32::MAX - 2).unwrap();
+    gv.inc_all(5).unwrap();
+    assert_eq!(gv.val("a").unwrap(), ::std::u32::MAX - 1);
+    assert!(gv.is_available("a"));
+
+    // a cleared gauge stays cleared
+    gv.clear("a").unwrap();
+    gv.inc("a", 1).unwrap();
+    gv.dec_all(1).unwrap();
+    assert!(!gv.is_available("a"));
+
+    let gv = GaugeVector::new_typed("gauge_vector_signed_bounds", 0i32, &["a"], "", "").unwrap();
+    gv.clear("a").unwrap();
+    gv.inc("a", 1).unwrap();
+    assert!(!gv.is_available("a"));
+    gv.set("a", ::std::i32::MIN + 1).unwrap();
+    gv.dec("a", 1).unwrap();
+    assert_eq!(gv.val("a").unwrap(), ::std::i32::MIN + 1);
+}
@@ -1,17 +1,29 @@
 use super::*;
-use hdrsample;
 use hdrsample::Histogram as HdrHist;
+use std::cmp;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 /// A histogram metric that records data and reports statistics
 ///
 /// Internally backed by a [HDR Histogram](https://github.com/jonhoo/hdrsample),
 /// much of API and documentation being borrowed from it.
 ///
-/// Exports the `max`, `min`, `mean` and `stdev` statistics to an MMV
-/// by using an `InstanceMetric<f64>` with `Semantics::Instant`.
+/// Exports the `max`, `min`, `mean` and `stdev` statistics, and values at
+/// chosen percentiles, to an MMV by using an `InstanceMetric<f64>` with
+/// `Semantics::Instant`. The instance of a percentile is named after it,
+/// e.g., `p50`, `p99` or `p99.9`.
+///
+/// The total count and sum of the recorded samples are exported as
+/// `<name>_count` and `<name>_sum` metrics with `Semantics::Counter`,
+/// so that rates and averages between samples can be computed. Both
+/// saturate at `u64::MAX - 1`, like a `Counter`.
 pub struct Histogram {
     im: InstanceMetric<f64>,
+    count: Metric<u64>,
+    sum: Metric<u64>,
     indom: Indom,
+    percentiles: Vec<(f64, String)>,
     histogram: HdrHist<u64>
 }
 
@@ -22,57 +34,35 @@ const STDEV_INST: &str = "stdev";
 
 const HIST_INSTANCES: &[&str] = &[MAX_INST, MIN_INST, MEAN_INST, STDEV_INST];
 
-/// Error encountered while creating a histogram
-#[derive(Debug)]
-pub enum CreationError {
-    /// Instance error
-    Instance(String),
-    /// HDR Histogram creation error
-    HdrHist(hdrsample::CreationError)
-}
-
-impl From<String> for CreationError {
-    fn from(err: String) -> CreationError {
-        CreationError::Instance(err)
-    }
-}
-
-impl From<hdrsample::CreationError> for CreationError {
-    fn from(err: hdrsample::CreationError) -> CreationError {
-        CreationError::HdrHist(err)
-    }
-}
-
-/// Error encountered while a histogram records values
-#[derive(Debug)]
-pub enum RecordError {
-    /// IO error
-    Io(io::Error),
-    /// HDR histogram record error
-    HdrHist(hdrsample::RecordError)
-}
-
-impl From<io::Error> for RecordError {
-    fn from(err: io::Error) -> RecordError {
-        RecordError::Io(err)
-    }
-}
-
-impl From<hdrsample::RecordError> for RecordError {
-    fn from(err: hdrsample::RecordError) -> RecordError {
-        RecordError::HdrHist(err)
-    }
-}
-
 impl Histogram {
     /// Creates a new histogram metric
     ///
     /// Internally creates a corresponding HDR histogram with auto-resizing disabled
     pub fn new(name: &str, low: u64, high: u64, sigfig: u8, unit: Unit,
-        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, CreationError> {
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
+        Self::new_with_percentiles(
+            name, low, high, sigfig, unit, &[],
+            shorthelp_text, longhelp_text
+        )
+    }
+
+    /// Creates a new histogram metric that also exports the values
+    /// at the given percentiles, e.g., `&[50.0, 90.0, 99.0, 99.9]`
+    ///
+    /// Internally creates a corresponding HDR histogram with auto-resizing disabled
+    pub fn new_with_percentiles(name: &str, low: u64, high: u64, sigfig: u8,
+        unit: Unit, percentiles: &[f64],
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
+
+        let percentiles: Vec<(f64, String)> = percentiles.iter()
+            .map(|&percentile| (percentile, format!("p{}", percentile)))
+            .collect();
+
+        let mut instances = HIST_INSTANCES.to_vec();
+        instances.extend(percentiles.iter().map(|&(_, ref inst)| inst.as_str()));
     
         let indom_helptext = format!("Instance domain for Histogram '{}'", name);
-        let indom = Indom::new(HIST_INSTANCES, &indom_helptext, &indom_helptext).unwrap();
+        let indom = Indom::new_owned(name, &instances, &indom_helptext, &indom_helptext)?;
         
         let im = InstanceMetric::new(
             &indom,
@@ -84,39 +74,68 @@ impl Histogram {
             longhelp_text
         )?;
 
+        let count_helptext = format!("Count of samples recorded by Histogram '{}'", name);
+        let count = Metric::new(
+            &format!("{}_count", name),
+            0,
+            Semantics::Counter,
+            Unit::new().count(Count::One, 1)?,
+            &count_helptext, &count_helptext
+        )?;
+
+        let sum_helptext = format!("Sum of samples recorded by Histogram '{}'", name);
+        let sum = Metric::new(
+            &format!("{}_sum", name),
+            0,
+            Semantics::Counter,
+            unit,
+            &sum_helptext, &sum_helptext
+        )?;
+
         let mut histogram = HdrHist::<u64>::new_with_bounds(low, high, sigfig)?;
         histogram.auto(false);
 
         Ok(Histogram {
             im: im,
+            count: count,
+            sum: sum,
             indom: indom,
+            percentiles: percentiles,
             histogram: histogram
         })
     }
 
-    fn update_instances(&mut self) -> io::Result<()> {
-        self.im.set_val(MIN_INST, self.histogram.min() as f64).unwrap()?;
-        self.im.set_val(MAX_INST, self.histogram.max() as f64).unwrap()?;
-        self.im.set_val(MEAN_INST, self.histogram.mean()).unwrap()?;
-        self.im.set_val(STDEV_INST, self.histogram.stdev()).unwrap()
+    fn update_instances(&mut self) -> Result<(), Error> {
+        self.im.set_val(MIN_INST, self.histogram.min() as f64)?;
+        self.im.set_val(MAX_INST, self.histogram.max() as f64)?;
+        self.im.set_val(MEAN_INST, self.histogram.mean())?;
+        self.im.set_val(STDEV_INST, self.histogram.stdev())?;
+        for &(percentile, ref inst) in self.percentiles.iter() {
+            let val = self.histogram.value_at_percentile(percentile);
+            self.im.set_val(inst, val as f64)?;
+        }
+        Ok(())
     }
 
     /// Records a value
-    pub fn record(&mut self, val: u64) -> Result<(), RecordError> {
-        self.histogram.record(val)?;
-        self.update_instances()?;
-        Ok(())
+    pub fn record(&mut self, val: u64) -> Result<(), Error> {
+        self.record_n(val, 1)
     }
 
     /// Records multiple samples of a single value
-    pub fn record_n(&mut self, val: u64, n: u64) -> Result<(), RecordError> {
+    pub fn record_n(&mut self, val: u64, n: u64) -> Result<(), Error> {
         self.histogram.record_n(val, n)?;
+        self.count.update(|count| count.saturating_inc(n))?;
+        self.sum.update(|sum| sum.saturating_inc(val.saturating_mul(n)))?;
         self.update_instances()?;
         Ok(())
     }
 
     /// Resets the contents and statistics of the histogram
-    pub fn reset(&mut self) -> io::Result<()> {
+    ///
+    /// The exported total count and sum aren't reset, since
+    /// they're counters.
+    pub fn reset(&mut self) -> Result<(), Error> {
         self.histogram.reset();
         self.update_instances()
     }
@@ -129,6 +148,12 @@ impl Histogram {
     pub fn significant_figures(&self) -> u8 { self.histogram.sigfig() }
     /// Total number of samples recorded so far
     pub fn count(&self) -> u64 { self.histogram.count() }
+    /// Total number of samples recorded since the histogram was
+    /// created, which isn't reset with `reset`
+    pub fn total_count(&self) -> u64 { self.count.val() }
+    /// Sum of samples recorded since the histogram was created,
+    /// which isn't reset with `reset`
+    pub fn total_sum(&self) -> u64 { self.sum.val() }
     /// Number of distinct values that can currently be represented
     pub fn len(&self) -> usize { self.histogram.len() }
 
@@ -153,6 +178,11 @@ impl Histogram {
         self.histogram.value_at_percentile(percentile)
     }
 
+    /// Percentiles whose values are exported
+    pub fn percentiles(&self) -> Vec<f64> {
+        self.percentiles.iter().map(|&(percentile, _)| percentile).collect()
+    }
+
     /// Control whether or not the histogram can auto-resize and auto-adjust
     /// it's highest trackable value as high-valued samples are recorded
     pub fn set_autoresize(&mut self, enable: bool) {
@@ -164,21 +194,292 @@ impl Histogram {
 
     /// Internally created HDR histogram
     pub fn hdr_histogram(&self) -> &HdrHist<u64> { &self.histogram }
+
+    /// Sets the item ID of the histogram's statistics, as with
+    /// `Metric::set_item`
+    ///
+    /// The count and sum metrics keep the item IDs derived from their names.
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        self.im.set_item(item)
+    }
 }
 
 impl MMVWriter for Histogram {
     private_impl!{}
 
-    fn write(&mut self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
-        self.im.write(ws, c, mmv_ver)
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+        self.im.write(ws, c, mmv_ver)?;
+        self.count.write(ws, c, mmv_ver)?;
+        self.sum.write(ws, c, mmv_ver)
     }
 
-    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) {
-        self.im.register(ws, mmv_ver)
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
+        self.im.register(ws, mmv_ver)?;
+        self.count.register(ws, mmv_ver)?;
+        self.sum.register(ws, mmv_ver)
     }
 
     fn has_mmv2_string(&self) -> bool {
         self.im.has_mmv2_string()
+            || self.count.has_mmv2_string()
+            || self.sum.has_mmv2_string()
+    }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        Box::new(CompoundWriter(vec![
+            self.im.share(), self.count.share(), self.sum.share()
+        ]))
+    }
+
+    fn id(&self) -> usize {
+        self.im.id()
+    }
+
+    fn name(&self) -> &str {
+        self.im.name()
+    }
+}
+
+/// A histogram metric that reports statistics of only the samples
+/// recorded within a recent time window
+///
+/// The window is split into slices, each backed by it's own HDR
+/// histogram. The slices form a ring, and as time passes, the oldest
+/// slice is cleared and reused for new samples. The exported statistics
+/// and percentiles are those of the slices merged together.
+///
+/// The window is moved forward when recording values, when calling
+/// `refresh`, or when the histogram is sampled by a client, e.g., with
+/// `Client::sample_every`, so that old samples drop out of the window even
+/// when nothing is recorded. The exported total count and sum cover every
+/// sample ever recorded, just like for a `Histogram`.
+///
+/// The histogram is updated atomically, so it can be shared between
+/// threads, e.g., with an `Arc<RollingHistogram>`.
+pub struct RollingHistogram {
+    state: Arc<Mutex<RollingState>>,
+    slice_len: Duration,
+    slice_count: usize,
+    indom: Indom,
+    name: String,
+    id: usize,
+    clock: Arc<Fn() -> Instant + Send + Sync>
+}
+
+struct RollingState {
+    hist: Histogram,
+    slices: Vec<HdrHist<u64>>,
+    current: usize,
+    slice_start: Instant
+}
+
+impl RollingState {
+    fn refresh(&mut self, now: Instant, slice_len: Duration) -> Result<(), Error> {
+        if now < self.slice_start + slice_len {
+            return Ok(())
+        }
+
+        let elapsed = duration_nanos(now.duration_since(self.slice_start));
+        let elapsed_slices = elapsed / cmp::max(duration_nanos(slice_len), 1);
+
+        let slice_count = self.slices.len();
+        if elapsed_slices >= slice_count as u64 {
+            for slice in self.slices.iter_mut() {
+                slice.reset();
+            }
+            self.slice_start = now;
+        } else {
+            for _ in 0..elapsed_slices {
+                self.current = (self.current + 1) % slice_count;
+                self.slices[self.current].reset();
+            }
+            self.slice_start += slice_len * elapsed_slices as u32;
+        }
+
+        self.hist.histogram.reset();
+        for slice in self.slices.iter() {
+            self.hist.histogram.add(slice)?;
+        }
+        self.hist.update_instances()
+    }
+}
+
+impl RollingHistogram {
+    /// Creates a new rolling histogram metric over the given window,
+    /// split into the given number of slices, that also exports the
+    /// values at the given percentiles
+    ///
+    /// E.g., a window of 60 seconds with 6 slices reports the samples
+    /// recorded within the last 50 to 60 seconds. At least one slice
+    /// is always used.
+    pub fn new(name: &str, low: u64, high: u64, sigfig: u8, unit: Unit,
+        window: Duration, slices: usize, percentiles: &[f64],
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
+        Self::new_with_clock(
+            name, low, high, sigfig, unit, window, slices, percentiles,
+            shorthelp_text, longhelp_text, Arc::new(Instant::now)
+        )
+    }
+
+    fn new_with_clock(name: &str, low: u64, high: u64, sigfig: u8, unit: Unit,
+        window: Duration, slices: usize, percentiles: &[f64],
+        shorthelp_text: &str, longhelp_text: &str,
+        clock: Arc<Fn() -> Instant + Send + Sync>) -> Result<Self, Error> {
+
+        let hist = Histogram::new_with_percentiles(
+            name, low, high, sigfig, unit, percentiles,
+            shorthelp_text, longhelp_text
+        )?;
+
+        let slice_count = cmp::max(slices, 1);
+        let slices = (0..slice_count)
+            .map(|_| HdrHist::new_from(&hist.histogram))
+            .collect();
+
+        Ok(RollingHistogram {
+            slice_len: window / slice_count as u32,
+            slice_count: slice_count,
+            indom: hist.indom.clone(),
+            name: hist.name().to_owned(),
+            id: hist.id(),
+            state: Arc::new(Mutex::new(RollingState {
+                hist: hist,
+                slices: slices,
+                current: 0,
+                slice_start: clock()
+            })),
+            clock: clock
+        })
+    }
+
+    fn lock_state(&self) -> MutexGuard<RollingState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records a value
+    pub fn record(&self, val: u64) -> Result<(), Error> {
+        self.record_n(val, 1)
+    }
+
+    /// Records multiple samples of a single value
+    pub fn record_n(&self, val: u64, n: u64) -> Result<(), Error> {
+        let mut state = self.lock_state();
+        state.refresh((self.clock)(), self.slice_len)?;
+        let current = state.current;
+        state.slices[current].record_n(val, n)?;
+        state.hist.record_n(val, n)
+    }
+
+    /// Moves the window forward to the current time, dropping the
+    /// samples that fell out of it, and updates the exported statistics
+    pub fn refresh(&self) -> Result<(), Error> {
+        self.lock_state().refresh((self.clock)(), self.slice_len)
+    }
+
+    /// Resets the contents and statistics of the histogram
+    ///
+    /// The exported total count and sum aren't reset, since
+    /// they're counters.
+    pub fn reset(&self) -> Result<(), Error> {
+        let mut state = self.lock_state();
+        for slice in state.slices.iter_mut() {
+            slice.reset();
+        }
+        state.hist.reset()
+    }
+
+    /// Length of the time window
+    pub fn window(&self) -> Duration { self.slice_len * self.slice_count as u32 }
+    /// Number of slices the window is split into
+    pub fn slice_count(&self) -> usize { self.slice_count }
+    /// Number of samples recorded within the window, as of
+    /// the last time it was moved forward
+    pub fn count(&self) -> u64 { self.lock_state().hist.count() }
+    /// Total number of samples recorded since the histogram was
+    /// created, which isn't reset with `reset`
+    pub fn total_count(&self) -> u64 { self.lock_state().hist.total_count() }
+    /// Sum of samples recorded since the histogram was created,
+    /// which isn't reset with `reset`
+    pub fn total_sum(&self) -> u64 { self.lock_state().hist.total_sum() }
+
+    /// Lowest value recorded within the window
+    ///
+    /// If no values are recorded `0` is returned
+    pub fn min(&self) -> u64 { self.lock_state().hist.min() }
+
+    /// Highest value recorded within the window
+    ///
+    /// If no values are recorded, an undefined value is returned
+    pub fn max(&self) -> u64 { self.lock_state().hist.max() }
+
+    /// Mean of values recorded within the window
+    pub fn mean(&self) -> f64 { self.lock_state().hist.mean() }
+
+    /// Standard deviation of values recorded within the window
+    pub fn stdev(&self) -> f64 { self.lock_state().hist.stdev() }
+
+    /// Returns corresponding value at percentile of the values
+    /// recorded within the window
+    pub fn value_at_percentile(&self, percentile: f64) -> u64 {
+        self.lock_state().hist.value_at_percentile(percentile)
+    }
+
+    /// Percentiles whose values are exported
+    pub fn percentiles(&self) -> Vec<f64> { self.lock_state().hist.percentiles() }
+
+    /// Internally created instance domain
+    pub fn indom(&self) -> &Indom { &self.indom }
+
+    /// Sets the item ID of the histogram's statistics, as with
+    /// `Metric::set_item`
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        self.lock_state().hist.set_item(item)
+    }
+}
+
+fn duration_nanos(duration: Duration) -> u64 {
+    duration.as_secs()
+        .saturating_mul(1_000_000_000)
+        .saturating_add(duration.subsec_nanos() as u64)
+}
+
+impl MMVWriter for RollingHistogram {
+    private_impl!{}
+
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+        self.lock_state().hist.write(ws, c, mmv_ver)
+    }
+
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
+        self.lock_state().hist.register(ws, mmv_ver)
+    }
+
+    fn has_mmv2_string(&self) -> bool {
+        self.lock_state().hist.has_mmv2_string()
+    }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        Box::new(RollingHistogram {
+            state: self.state.clone(),
+            slice_len: self.slice_len,
+            slice_count: self.slice_count,
+            indom: self.indom.clone(),
+            name: self.name.clone(),
+            id: self.id,
+            clock: self.clock.clone()
+        })
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sample(&self) -> Result<(), Error> {
+        self.refresh()
     }
 }
 
@@ -192,24 +493,32 @@ pub fn test() {
     let high = 60 * 60 * 1000;
     let sigfig = 2;
 
-    let mut hist = Histogram::new(
+    let mut hist = Histogram::new_with_percentiles(
         "histogram",
         low, high, sigfig,
         Unit::new(),
+        &[50.0, 99.9],
         "", ""
     ).unwrap();
+    assert!(hist.indom().has_instance("p50"));
+    assert!(hist.indom().has_instance("p99.9"));
 
-    Client::new("histogram_test").unwrap()
-        .export(&mut [&mut hist]).unwrap();
+    let client = Client::new("histogram_test").unwrap();
+    client.export(&mut [&mut hist]).unwrap();
     
     let val_range = Range::new(low, high);
     let mut rng = thread_rng();
 
     let n = thread_rng().gen::<u64>() % 100;
+    let mut sum = 0;
     for _ in 0..n { 
-        hist.record(val_range.ind_sample(&mut rng)).unwrap();
+        let val = val_range.ind_sample(&mut rng);
+        sum += val;
+        hist.record(val).unwrap();
     }
-    hist.record_n(val_range.ind_sample(&mut rng), n).unwrap();
+    let val = val_range.ind_sample(&mut rng);
+    sum += val * n;
+    hist.record_n(val, n).unwrap();
 
     assert_eq!(
         hist.im.val(MIN_INST).unwrap(),
@@ -230,4 +539,112 @@ pub fn test() {
         hist.im.val(STDEV_INST).unwrap(),
         hist.histogram.stdev()
     );
+
+    assert_eq!(
+        hist.im.val("p50").unwrap(),
+        hist.histogram.value_at_percentile(50.0) as f64
+    );
+
+    assert_eq!(
+        hist.im.val("p99.9").unwrap(),
+        hist.histogram.value_at_percentile(99.9) as f64
+    );
+
+    assert_eq!(hist.total_count(), 2 * n);
+    assert_eq!(hist.total_sum(), sum);
+
+    hist.reset().unwrap();
+    assert_eq!(hist.count(), 0);
+    assert_eq!(hist.total_count(), 2 * n);
+
+    let mmv = super::super::super::mmv::dump(client.mmv_path()).unwrap();
+    assert_eq!(mmv.metric_blks().len(), 3);
+
+    // the count and sum saturate short of the sentinel
+    hist.count.set_val(u64::max_value() - 1).unwrap();
+    hist.sum.set_val(u64::max_value() - 2).unwrap();
+    hist.record(high).unwrap();
+    assert_eq!(hist.total_count(), u64::max_value() - 1);
+    assert_eq!(hist.total_sum(), u64::max_value() - 1);
+}
+
+#[test]
+pub fn test_rolling() {
+    use super::super::Client;
+    use super::super::super::mmv::{dump, Value};
+
+    let start = Instant::now();
+    let now = Arc::new(Mutex::new(start));
+    let clock_now = now.clone();
+    let advance_to = |secs: u64| *now.lock().unwrap() = start + Duration::from_secs(secs);
+
+    let mut hist = RollingHistogram::new_with_clock(
+        "rolling_histogram",
+        1, 1000, 2,
+        Unit::new(),
+        Duration::from_secs(60), 6,
+        &[50.0],
+        "", "",
+        Arc::new(move || *clock_now.lock().unwrap())
+    ).unwrap();
+    assert_eq!(hist.window(), Duration::from_secs(60));
+    assert_eq!(hist.slice_count(), 6);
+
+    let client = Client::new("rolling_histogram_test").unwrap();
+    client.export(&mut [&mut hist]).unwrap();
+
+    hist.record(500).unwrap();
+    advance_to(30);
+    hist.record(10).unwrap();
+    assert_eq!(hist.count(), 2);
+    assert_eq!(hist.max(), 500);
+
+    // the first slice falls out of the window, without any traffic
+    advance_to(65);
+    client.sample().unwrap();
+    assert_eq!(hist.count(), 1);
+    assert_eq!(hist.max(), 10);
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert_eq!(mmv.get("rolling_histogram", Some(MAX_INST)),
+        Some(Value::F64(10.0)));
+    assert_eq!(mmv.get("rolling_histogram", Some("p50")),
+        Some(Value::F64(10.0)));
+    assert_eq!(hist.total_count(), 2);
+    assert_eq!(hist.total_sum(), 510);
+
+    // every slice falls out of the window
+    advance_to(200);
+    hist.refresh().unwrap();
+    assert_eq!(hist.count(), 0);
+    assert_eq!(hist.total_count(), 2);
+
+    hist.record(20).unwrap();
+    assert_eq!(hist.count(), 1);
+}
+
+#[test]
+pub fn test_shared_percentiles() {
+    use super::super::Client;
+    use super::super::super::mmv::{dump, Value};
+
+    let mut latency = Histogram::new_with_percentiles("histogram_latency", 1, 1000, 2,
+        Unit::new(), &[50.0, 99.9], "", "").unwrap();
+    let mut size = Histogram::new_with_percentiles("histogram_size", 1, 1000, 2,
+        Unit::new(), &[50.0, 99.9], "", "").unwrap();
+
+    let client = Client::new("histogram_shared_percentiles_test").unwrap();
+    client.export(&mut [&mut latency, &mut size]).unwrap();
+
+    latency.record(10).unwrap();
+    size.record(500).unwrap();
+
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert_eq!(mmv.indom_blks().len(), 2);
+    for &inst in &[MIN_INST, MAX_INST, "p50", "p99.9"] {
+        assert_eq!(mmv.get("histogram_latency", Some(inst)),
+            Some(Value::F64(latency.im.val(inst).unwrap())));
+        assert_eq!(mmv.get("histogram_size", Some(inst)),
+            Some(Value::F64(size.im.val(inst).unwrap())));
+    }
+    assert_eq!(mmv.get("histogram_latency", Some("p50")), Some(Value::F64(10.0)));
 }
@@ -0,0 +1,319 @@
+use super::*;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// A meter metric that counts events and reports the rate at which
+/// they occur
+///
+/// Exports the `1min`, `5min` and `15min` exponentially weighted moving
+/// average rates, and the `mean` rate since the meter was created, in
+/// events per second, by using an `InstanceMetric<f64>` with
+/// `Semantics::Instant`.
+///
+/// The total count of events is exported as a `<name>_count` metric with
+/// `Semantics::Counter`.
+///
+/// The moving averages are updated every 5 seconds. Marking events only
+/// writes the count, and the rates are written together when calling
+/// `refresh`, or when the meter is sampled by a client, e.g., with
+/// `Client::sample_every`, so that the rates decay even when no events
+/// occur.
+///
+/// The meter is updated atomically, so it can be shared between
+/// threads, e.g., with an `Arc<Meter>`.
+pub struct Meter {
+    im: InstanceMetric<f64>,
+    count: Metric<u64>,
+    indom: Indom,
+    state: Arc<Mutex<MeterState>>
+}
+
+struct MeterState {
+    start_time: Instant,
+    last_tick: Instant,
+    uncounted: u64,
+    total: u64,
+    rates: [Ewma; 3]
+}
+
+// an exponentially weighted moving average of a per-second rate
+struct Ewma {
+    alpha: f64,
+    rate: Option<f64>
+}
+
+const M1_INST: &str = "1min";
+const M5_INST: &str = "5min";
+const M15_INST: &str = "15min";
+const MEAN_INST: &str = "mean";
+
+const METER_INSTANCES: &[&str] = &[M1_INST, M5_INST, M15_INST, MEAN_INST];
+
+const TICK_SECS: u64 = 5;
+
+impl Ewma {
+    fn new(minutes: f64) -> Self {
+        Ewma {
+            alpha: 1.0 - (-(TICK_SECS as f64) / 60.0 / minutes).exp(),
+            rate: None
+        }
+    }
+
+    fn tick(&mut self, count: u64) {
+        let instant_rate = count as f64 / TICK_SECS as f64;
+        self.rate = Some(match self.rate {
+            Some(rate) => rate + self.alpha * (instant_rate - rate),
+            None => instant_rate
+        });
+    }
+
+    fn rate(&self) -> f64 {
+        self.rate.unwrap_or(0.0)
+    }
+}
+
+impl MeterState {
+    fn tick(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_tick).as_secs();
+        let ticks = elapsed / TICK_SECS;
+        for _ in 0..ticks {
+            let uncounted = self.uncounted;
+            for rate in self.rates.iter_mut() {
+                rate.tick(uncounted);
+            }
+            self.uncounted = 0;
+        }
+        self.last_tick += Duration::from_secs(ticks * TICK_SECS);
+    }
+}
+
+impl Meter {
+    /// Creates a new meter metric
+    pub fn new(name: &str, shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
+        let indom_helptext = format!("Instance domain for Meter '{}'", name);
+        let indom = Indom::new_owned(name, METER_INSTANCES, &indom_helptext, &indom_helptext)?;
+
+        let im = InstanceMetric::new(
+            &indom,
+            name,
+            0.0,
+            Semantics::Instant,
+            Unit::new().count(Count::One, 1)?.time(Time::Sec, -1)?,
+            shorthelp_text,
+            longhelp_text
+        )?;
+
+        let count_helptext = format!("Count of events marked by Meter '{}'", name);
+        let count = Metric::new(
+            &format!("{}_count", name),
+            0,
+            Semantics::Counter,
+            Unit::new().count(Count::One, 1)?,
+            &count_helptext, &count_helptext
+        )?;
+
+        let now = Instant::now();
+        Ok(Meter {
+            im: im,
+            count: count,
+            indom: indom,
+            state: Arc::new(Mutex::new(MeterState {
+                start_time: now,
+                last_tick: now,
+                uncounted: 0,
+                total: 0,
+                rates: [Ewma::new(1.0), Ewma::new(5.0), Ewma::new(15.0)]
+            }))
+        })
+    }
+
+    /// Marks the occurence of an event
+    pub fn mark(&self) -> Result<(), Error> {
+        self.mark_n(1)
+    }
+
+    /// Marks the occurence of multiple events
+    pub fn mark_n(&self, n: u64) -> Result<(), Error> {
+        self.mark_n_at(n, Instant::now())
+    }
+
+    fn mark_n_at(&self, n: u64, now: Instant) -> Result<(), Error> {
+        let mut state = self.lock_state();
+        state.tick(now);
+        state.uncounted = state.uncounted.wrapping_add(n);
+        state.total = state.total.wrapping_add(n);
+        self.count.set_val(state.total)?;
+        Ok(())
+    }
+
+    /// Moves the moving averages forward to the current time and
+    /// updates the exported rates
+    pub fn refresh(&self) -> Result<(), Error> {
+        self.refresh_at(Instant::now())
+    }
+
+    fn refresh_at(&self, now: Instant) -> Result<(), Error> {
+        let mut state = self.lock_state();
+        state.tick(now);
+        self.update_instances(&state, now)
+    }
+
+    fn lock_state(&self) -> MutexGuard<MeterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update_instances(&self, state: &MeterState, now: Instant) -> Result<(), Error> {
+        self.im.set_val(M1_INST, state.rates[0].rate())?;
+        self.im.set_val(M5_INST, state.rates[1].rate())?;
+        self.im.set_val(M15_INST, state.rates[2].rate())?;
+        self.im.set_val(MEAN_INST, mean_rate(state.total, now.duration_since(state.start_time)))
+    }
+
+    /// Total number of events marked so far
+    pub fn count(&self) -> u64 { self.count.val() }
+
+    /// Moving average rate of events per second over the last minute
+    pub fn one_minute_rate(&self) -> f64 { self.lock_state().rates[0].rate() }
+
+    /// Moving average rate of events per second over the last 5 minutes
+    pub fn five_minute_rate(&self) -> f64 { self.lock_state().rates[1].rate() }
+
+    /// Moving average rate of events per second over the last 15 minutes
+    pub fn fifteen_minute_rate(&self) -> f64 { self.lock_state().rates[2].rate() }
+
+    /// Mean rate of events per second since the meter was created
+    pub fn mean_rate(&self) -> f64 {
+        let state = self.lock_state();
+        mean_rate(state.total, state.start_time.elapsed())
+    }
+
+    /// Internally created instance domain
+    pub fn indom(&self) -> &Indom { &self.indom }
+
+    /// Sets the item ID of the meter's rates, as with `Metric::set_item`
+    ///
+    /// The count metric keeps the item ID derived from it's name.
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        self.im.set_item(item)
+    }
+}
+
+fn mean_rate(count: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+    if secs > 0.0 { count as f64 / secs } else { 0.0 }
+}
+
+impl MMVWriter for Meter {
+    private_impl!{}
+
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+        self.im.write(ws, c, mmv_ver)?;
+        self.count.write(ws, c, mmv_ver)
+    }
+
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
+        self.im.register(ws, mmv_ver)?;
+        self.count.register(ws, mmv_ver)
+    }
+
+    fn has_mmv2_string(&self) -> bool {
+        self.im.has_mmv2_string() || self.count.has_mmv2_string()
+    }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        Box::new(Meter {
+            im: self.im.clone_shared(),
+            count: self.count.clone_shared(),
+            indom: self.indom.clone(),
+            state: self.state.clone()
+        })
+    }
+
+    fn id(&self) -> usize {
+        self.im.id()
+    }
+
+    fn name(&self) -> &str {
+        self.im.name()
+    }
+
+    fn sample(&self) -> Result<(), Error> {
+        self.refresh()
+    }
+}
+
+#[test]
+pub fn test() {
+    use super::super::Client;
+
+    let mut meter = Meter::new("meter", "", "").unwrap();
+    assert_eq!(meter.indom().instance_count(), 4);
+
+    let client = Client::new("meter_test").unwrap();
+    client.export(&mut [&mut meter]).unwrap();
+
+    let start = meter.lock_state().start_time;
+    meter.mark_n_at(50, start).unwrap();
+    for _ in 0..10 {
+        meter.mark_n_at(1, start + Duration::from_secs(1)).unwrap();
+    }
+    assert_eq!(meter.count(), 60);
+    // marking doesn't write the rates
+    assert_eq!(meter.im.val(MEAN_INST).unwrap(), 0.0);
+
+    // first tick sets the rates to the instant rate
+    meter.refresh_at(start + Duration::from_secs(TICK_SECS)).unwrap();
+    assert_eq!(meter.one_minute_rate(), 12.0);
+    assert_eq!(meter.fifteen_minute_rate(), 12.0);
+    assert_eq!(meter.im.val(M5_INST).unwrap(), 12.0);
+    assert_eq!(meter.im.val(MEAN_INST).unwrap(), 12.0);
+
+    // rates decay without events, faster over shorter windows
+    meter.refresh_at(start + Duration::from_secs(60 + TICK_SECS)).unwrap();
+    assert!(meter.one_minute_rate() < meter.five_minute_rate());
+    assert!(meter.five_minute_rate() < meter.fifteen_minute_rate());
+    assert!(meter.fifteen_minute_rate() < 12.0);
+    assert_eq!(meter.im.val(M1_INST).unwrap(), meter.one_minute_rate());
+    assert_eq!(meter.count(), 60);
+}
+
+#[test]
+pub fn test_sample() {
+    use super::super::Client;
+
+    let mut meter = Meter::new("meter_sample", "", "").unwrap();
+    let client = Client::new("meter_sample_test").unwrap();
+    client.export(&mut [&mut meter]).unwrap();
+
+    // sampling refreshes the meter, so the mean rate decays without events
+    meter.lock_state().start_time -= Duration::from_secs(10);
+    meter.lock_state().total = 10;
+    client.sample().unwrap();
+    let mean_rate = meter.im.val(MEAN_INST).unwrap();
+    assert!(mean_rate > 0.0 && mean_rate <= 1.0);
+}
+
+#[test]
+pub fn test_shared_instances() {
+    use super::super::Client;
+    use super::super::super::mmv::{dump, Value};
+
+    let mut requests = Meter::new("meter_requests", "", "").unwrap();
+    let mut errors = Meter::new("meter_errors", "", "").unwrap();
+    assert!(requests.indom().id != errors.indom().id);
+
+    let client = Client::new("meter_shared_instances_test").unwrap();
+    client.export(&mut [&mut requests, &mut errors]).unwrap();
+
+    // each rate is bound to it's own meter's instance
+    for (i, &inst) in METER_INSTANCES.iter().enumerate() {
+        requests.im.set_val(inst, i as f64).unwrap();
+        errors.im.set_val(inst, 10.0 + i as f64).unwrap();
+    }
+    let mmv = dump(client.mmv_path()).unwrap();
+    for (i, &inst) in METER_INSTANCES.iter().enumerate() {
+        assert_eq!(mmv.get("meter_requests", Some(inst)), Some(Value::F64(i as f64)));
+        assert_eq!(mmv.get("meter_errors", Some(inst)), Some(Value::F64(10.0 + i as f64)));
+    }
+    assert_eq!(mmv.indom_blks().len(), 2);
+}
@@ -1,5 +1,5 @@
 use byteorder::WriteBytesExt;
-use memmap::{Mmap, MmapViewSync, Protection};
+use memmap::MmapViewSync;
 use std::collections::HashSet;
 use std::collections::hash_map::{DefaultHasher, HashMap};
 use std::collections::hash_set::Iter;
@@ -7,12 +7,16 @@ use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::io;
 use std::io::{Write, Cursor};
+use std::marker::PhantomData;
 use std::mem;
 use std::str;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
+use super::ExportLink;
 use super::super::mmv::{MTCode, Version};
 use super::super::{
     Endian,
+    Error,
     ITEM_BIT_LEN,
     INDOM_BIT_LEN,
     STRING_BLOCK_LEN,
@@ -33,7 +37,7 @@ mod gauge;
 pub use self::gauge::Gauge;
 
 mod timer;
-pub use self::timer::Timer;
+pub use self::timer::{Timer, TimerGuard};
 
 mod countvector;
 pub use self::countvector::CountVector;
@@ -42,16 +46,36 @@ mod gaugevector;
 pub use self::gaugevector::GaugeVector;
 
 mod histogram;
-pub use self::histogram::Histogram;
-pub use self::histogram::CreationError as HistCreationError;
-pub use self::histogram::RecordError as HistRecordError;
+pub use self::histogram::{Histogram, RollingHistogram};
+
+mod meter;
+pub use self::meter::Meter;
+
+mod buildinfo;
+pub use self::buildinfo::BuildInfo;
+
+mod family;
+pub use self::family::{Family, LabelValues};
+
+mod process;
+pub use self::process::ProcessMetrics;
+pub (crate) use self::process::process_start_time;
+
+mod callback;
+pub use self::callback::CallbackMetric;
+
+mod builder;
+pub use self::builder::MetricBuilder;
+
+mod namespace;
+pub use self::namespace::Namespace;
 
 mod private {
     use byteorder::WriteBytesExt;
     use std::io;
 
     /// Generic type for any Metric's value
-    pub trait MetricType {
+    pub trait MetricType: Send + Sync + 'static {
         private_decl!{}
 
         /// Returns the MMV metric type code
@@ -62,15 +86,165 @@ mod private {
         ///
         /// For the string type, the UTF-8 byte sequence is suffixed with a null byte.
         fn write<W: WriteBytesExt>(&self, writer: &mut W) -> io::Result<()>;
+        /// Writes the value to a slot
+        fn store(&self, slot: &Slot) -> io::Result<()>;
+        /// Reads the value held in a slot
+        fn load(slot: &Slot) -> Self where Self: Sized;
+        /// Replaces the value held in a slot with `f` applied to it, and
+        /// returns the new value
+        fn update<F: FnMut(Self) -> Self>(slot: &Slot, f: F) -> io::Result<Self> where Self: Sized;
+        /// Returns the value that's interpreted as "no value available"
+        /// when the `SENTINEL` flag is set
+        fn sentinel() -> Self where Self: Sized;
+        /// Checks if the value is the sentinel value
+        fn is_sentinel(&self) -> bool;
+    }
+
+    use std::ops::{Add, Sub};
+
+    /// Generic type for any numeric Metric's value
+    pub trait NumericType: MetricType + Copy + Add<Output=Self> + Sub<Output=Self> {
+        /// Returns the value `0`
+        fn zero() -> Self;
+        /// Returns the value `1`
+        fn one() -> Self;
+        /// Returns `self + rhs`, saturating at the bounds of the type
+        /// short of the sentinel value. The sentinel value itself is
+        /// returned unchanged.
+        fn saturating_inc(self, rhs: Self) -> Self;
+        /// Returns `self - rhs`, saturating at the bounds of the type
+        /// short of the sentinel value. The sentinel value itself is
+        /// returned unchanged.
+        fn saturating_dec(self, rhs: Self) -> Self;
     }
 
     use memmap::MmapViewSync;
+    use std::cmp;
+    use std::ptr;
+    use std::slice;
+    use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    enum SlotView {
+        // buffer owned by a metric that hasn't been exported yet
+        Local(Box<[AtomicU64]>),
+        // the value's location in an exported MMV
+        Mapped(MmapViewSync)
+    }
+
+    impl SlotView {
+        fn ptr(&self) -> *mut u8 {
+            match *self {
+                SlotView::Local(ref buf) => buf.as_ptr() as *mut u8,
+                SlotView::Mapped(ref view) => view.ptr() as *mut u8
+            }
+        }
+
+        fn len(&self) -> usize {
+            match *self {
+                SlotView::Local(ref buf) => buf.len() * 8,
+                SlotView::Mapped(ref view) => view.len()
+            }
+        }
+
+        // numeric values are 8-byte aligned both in the local buffer
+        // and in the MMV, since every MMV block length is a multiple of 8
+        fn bits(&self) -> &AtomicU64 {
+            unsafe { &*(self.ptr() as *const AtomicU64) }
+        }
+    }
+
+    /// Memory that holds a single metric or instance value
+    ///
+    /// Numeric values are read and written with atomic operations, so a
+    /// slot can be updated from many threads through a shared reference.
+    /// The lock is only held exclusively while a string value is rewritten,
+    /// or while the slot is re-pointed at it's location in an MMV.
+    pub struct Slot {
+        view: RwLock<SlotView>
+    }
+
+    impl Slot {
+        /// Creates a slot with a zeroed buffer of at least `len` bytes
+        pub fn new(len: usize) -> Self {
+            let words = (0..(len + 7)/8).map(|_| AtomicU64::new(0)).collect::<Vec<_>>();
+            Slot {
+                view: RwLock::new(SlotView::Local(words.into_boxed_slice()))
+            }
+        }
+
+        // the view is plain memory, so a panic while holding the lock
+        // can't leave it in an inconsistent state
+        fn read(&self) -> RwLockReadGuard<SlotView> {
+            self.view.read().unwrap_or_else(|e| e.into_inner())
+        }
+
+        fn write(&self) -> RwLockWriteGuard<SlotView> {
+            self.view.write().unwrap_or_else(|e| e.into_inner())
+        }
+
+        pub fn load_bits(&self) -> u64 {
+            self.read().bits().load(Ordering::SeqCst)
+        }
+
+        pub fn store_bits(&self, bits: u64) {
+            self.read().bits().store(bits, Ordering::SeqCst)
+        }
+
+        /// Atomically replaces the bits with `f` applied to them, and
+        /// returns the new bits
+        pub fn update_bits<F: FnMut(u64) -> u64>(&self, mut f: F) -> u64 {
+            let view = self.read();
+            let bits = view.bits();
+            let mut old = bits.load(Ordering::SeqCst);
+            loop {
+                let new = f(old);
+                match bits.compare_exchange_weak(old, new, Ordering::SeqCst, Ordering::SeqCst) {
+                    Ok(_) => return new,
+                    Err(actual) => old = actual
+                }
+            }
+        }
+
+        /// Calls `f` with shared access to the slot's bytes
+        pub fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+            let view = self.read();
+            f(unsafe { slice::from_raw_parts(view.ptr(), view.len()) })
+        }
+
+        /// Calls `f` with exclusive access to the slot's bytes
+        pub fn with_bytes_mut<R, F: FnOnce(&mut [u8]) -> R>(&self, f: F) -> R {
+            let view = self.write();
+            f(unsafe { slice::from_raw_parts_mut(view.ptr(), view.len()) })
+        }
+
+        /// Re-points the slot at `new_view`, carrying over the current value
+        pub fn remap(&self, new_view: MmapViewSync) {
+            let mut view = self.write();
+            let len = cmp::min(view.len(), new_view.len());
+            unsafe { ptr::copy_nonoverlapping(view.ptr(), new_view.ptr() as *mut u8, len); }
+            *view = SlotView::Mapped(new_view);
+        }
+    }
+
     use std::collections::HashMap;
-    
+    use std::sync::Arc;
+
+    /// Instances of an instance metric as of when it was registered,
+    /// which are the ones written even if they change in the meantime,
+    /// since the MMV is sized for them
+    pub struct InstancesSnapshot {
+        pub indom: super::Indom,
+        pub slots: Vec<(String, Arc<Slot>)>
+    }
+
     pub struct MMVWriterState {
         // Mmap view of the entier MMV file
         pub mmap_view: Option<MmapViewSync>,
 
+        // link to the export that's writing the MMV
+        pub (crate) export: Option<ExportLink>,
+
         // generation numbers
         pub gen: i64,
         pub gen2_off: u64,
@@ -87,8 +261,16 @@ mod private {
         pub non_value_string_cache: HashMap<String, Option<u64>>, // (string, offset to it)
         // if the offset is None, it means the string hasn't been written yet
         //
-        pub indom_cache: HashMap<u32, Option<Vec<u64>>>, // (indom_id, offsets to it's instances)
-        // if the offsets vector is None, it means the instances haven't been written yet
+        pub indom_cache: HashMap<u32, Option<HashMap<String, u64>>>, // (indom_id, (instance, offset to it))
+        // if the offsets map is None, it means the instances haven't been written yet
+        //
+        pub metric_items: HashMap<u32, String>, // (item, name of the metric using it)
+        pub metric_names: HashMap<String, u32>, // (metric name, item it's written with)
+        pub hashed_items: Vec<(String, u32)>, // (metric name, hashed item)
+        // metrics without an item set are assigned one once every metric
+        // is registered, so that they don't take the items that are set
+        //
+        pub instance_snapshots: HashMap<usize, InstancesSnapshot>, // (writer id, it's instances when registered)
 
         // offsets to blocks
         pub indom_sec_off: u64,
@@ -115,6 +297,8 @@ mod private {
             MMVWriterState {
                 mmap_view: None,
 
+                export: None,
+
                 gen: 0,
                 gen2_off: 0,
 
@@ -127,6 +311,10 @@ mod private {
 
                 indom_cache: HashMap::new(),
                 non_value_string_cache: HashMap::new(),
+                metric_items: HashMap::new(),
+                metric_names: HashMap::new(),
+                hashed_items: Vec::new(),
+                instance_snapshots: HashMap::new(),
 
                 indom_sec_off: 0,
                 instance_sec_off: 0,
@@ -147,27 +335,45 @@ mod private {
         }
     }
 
-    use super::Version;
+    use super::{Error, Version};
+    use super::super::ExportLink;
 
     /// MMV object that writes blocks to an MMV
     pub trait MMVWriter {
         private_decl!{}
 
-        fn write(&mut self,
+        fn write(&self,
             writer_state: &mut MMVWriterState,
             cursor: &mut io::Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()>;
 
-        fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version);
+        fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error>;
 
         fn has_mmv2_string(&self) -> bool;
+
+        /// Returns a writer that shares this object's values, which a
+        /// client keeps so it can regenerate the MMV later on
+        fn share(&self) -> Box<MMVWriter + Send + Sync>;
+
+        /// Identifies the values shared by this object and the
+        /// writers returned by `share`
+        fn id(&self) -> usize;
+
+        /// Returns the name of the metric written
+        fn name(&self) -> &str;
+
+        /// Updates the values that're pulled rather than set, which
+        /// a client does periodically if it has a sampler running
+        fn sample(&self) -> Result<(), Error> {
+            Ok(())
+        }
     }
 }
 
-pub (super) use self::private::MetricType;
-pub (super) use self::private::{MMVWriter, MMVWriterState};
+pub (super) use self::private::{MetricType, NumericType};
+pub (super) use self::private::{InstancesSnapshot, MMVWriter, MMVWriterState, Slot};
 
 macro_rules! impl_metric_type_for (
-    ($typ:tt, $base_typ:tt, $type_code:expr) => (
+    ($typ:tt, $base_typ:tt, $type_code:expr, $sentinel:expr, $is_sentinel:expr) => (
         impl MetricType for $typ {
 
             private_impl!{}
@@ -185,16 +391,110 @@ macro_rules! impl_metric_type_for (
                 )
             }
 
+            fn store(&self, slot: &Slot) -> io::Result<()> {
+                slot.store_bits(unsafe {
+                    mem::transmute::<$typ, $base_typ>(*self) as u64
+                });
+                Ok(())
+            }
+
+            fn load(slot: &Slot) -> Self {
+                unsafe {
+                    mem::transmute::<$base_typ, $typ>(slot.load_bits() as $base_typ)
+                }
+            }
+
+            fn update<F: FnMut(Self) -> Self>(slot: &Slot, mut f: F) -> io::Result<Self> {
+                let bits = slot.update_bits(|bits| unsafe {
+                    let val = mem::transmute::<$base_typ, $typ>(bits as $base_typ);
+                    mem::transmute::<$typ, $base_typ>(f(val)) as u64
+                });
+                Ok(unsafe { mem::transmute::<$base_typ, $typ>(bits as $base_typ) })
+            }
+
+            fn sentinel() -> Self {
+                $sentinel
+            }
+
+            fn is_sentinel(&self) -> bool {
+                ($is_sentinel)(*self)
+            }
+
         }
     )
 );
 
-impl_metric_type_for!(i32, u32, MTCode::I32);
-impl_metric_type_for!(u32, u32, MTCode::U32);
-impl_metric_type_for!(i64, u64, MTCode::I64);
-impl_metric_type_for!(u64, u64, MTCode::U64);
-impl_metric_type_for!(f32, u32, MTCode::F32);
-impl_metric_type_for!(f64, u64, MTCode::F64);
+// sentinel values are the minimum of signed integers, the maximum
+// of unsigned integers and NaN for floats, as expected by pmdammv
+impl_metric_type_for!(i32, u32, MTCode::I32,
+    ::std::i32::MIN, |val| val == ::std::i32::MIN);
+impl_metric_type_for!(u32, u32, MTCode::U32,
+    ::std::u32::MAX, |val| val == ::std::u32::MAX);
+impl_metric_type_for!(i64, u64, MTCode::I64,
+    ::std::i64::MIN, |val| val == ::std::i64::MIN);
+impl_metric_type_for!(u64, u64, MTCode::U64,
+    ::std::u64::MAX, |val| val == ::std::u64::MAX);
+impl_metric_type_for!(f32, u32, MTCode::F32,
+    ::std::f32::NAN, |val: f32| val.is_nan());
+impl_metric_type_for!(f64, u64, MTCode::F64,
+    ::std::f64::NAN, |val: f64| val.is_nan());
+
+macro_rules! impl_numeric_type_for (
+    (int $typ:tt, $short_of_sentinel:expr) => (
+        impl NumericType for $typ {
+            fn zero() -> Self {
+                0
+            }
+
+            fn one() -> Self {
+                1
+            }
+
+            fn saturating_inc(self, rhs: Self) -> Self {
+                if self.is_sentinel() {
+                    return self;
+                }
+                let sum = <$typ>::saturating_add(self, rhs);
+                if sum.is_sentinel() { $short_of_sentinel } else { sum }
+            }
+
+            fn saturating_dec(self, rhs: Self) -> Self {
+                if self.is_sentinel() {
+                    return self;
+                }
+                let diff = <$typ>::saturating_sub(self, rhs);
+                if diff.is_sentinel() { $short_of_sentinel } else { diff }
+            }
+        }
+    );
+    // floats don't overflow, and NaN stays NaN
+    (float $typ:tt) => (
+        impl NumericType for $typ {
+            fn zero() -> Self {
+                0.0
+            }
+
+            fn one() -> Self {
+                1.0
+            }
+
+            fn saturating_inc(self, rhs: Self) -> Self {
+                self + rhs
+            }
+
+            fn saturating_dec(self, rhs: Self) -> Self {
+                self - rhs
+            }
+        }
+    )
+);
+
+impl_numeric_type_for!(int i32, ::std::i32::MIN + 1);
+impl_numeric_type_for!(int u32, ::std::u32::MAX - 1);
+impl_numeric_type_for!(int i64, ::std::i64::MIN + 1);
+impl_numeric_type_for!(int u64, ::std::u64::MAX - 1);
+impl_numeric_type_for!(float f32);
+impl_numeric_type_for!(float f64);
 
 impl MetricType for String {
     private_impl!{}
@@ -207,6 +507,57 @@ impl MetricType for String {
         writer.write_all(self.as_bytes())?;
         writer.write_all(&[0])
     }
+
+    fn store(&self, slot: &Slot) -> io::Result<()> {
+        slot.with_bytes_mut(|bytes| write_string_value(self, bytes))
+    }
+
+    fn load(slot: &Slot) -> Self {
+        slot.with_bytes(read_string_value)
+    }
+
+    fn update<F: FnMut(Self) -> Self>(slot: &Slot, mut f: F) -> io::Result<Self> {
+        slot.with_bytes_mut(|bytes| {
+            let new_val = f(read_string_value(bytes));
+            write_string_value(&new_val, bytes)?;
+            Ok(new_val)
+        })
+    }
+
+    // the empty string is the sentinel for strings
+    fn sentinel() -> Self {
+        String::new()
+    }
+
+    fn is_sentinel(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+fn write_string_value(string: &str, bytes: &mut [u8]) -> io::Result<()> {
+    if string.len() >= bytes.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("string value longer than {} bytes", bytes.len() - 1)));
+    }
+    bytes[..string.len()].copy_from_slice(string.as_bytes());
+    bytes[string.len()] = 0;
+    Ok(())
+}
+
+fn read_string_value(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+// creates a slot big enough for values of the given type, holding `val`
+fn new_slot<T: MetricType>(val: &T) -> io::Result<Slot> {
+    let slot = if val.type_code() == MTCode::String as u32 {
+        Slot::new(STRING_BLOCK_LEN as usize)
+    } else {
+        Slot::new(NUMERIC_VALUE_SIZE)
+    };
+    val.store(&slot)?;
+    Ok(slot)
 }
 
 #[derive(Copy, Clone)]
@@ -352,7 +703,7 @@ const LS_FOUR_BIT_MASK: u32 = 0xF;
 macro_rules! check_dim (
     ($dim:expr) => (
         if $dim > 7 || $dim < -8 {
-            return Err(format!("Unit dimension {} is out of range [-8, 7]", $dim))
+            return Err(Error::InvalidUnitDimension($dim))
         }
     )
 );
@@ -372,7 +723,7 @@ impl Unit {
     }
 
     /// Modifies and returns the unit with given space scale and dimension
-    pub fn space(mut self, scale: Space, dim: i8) -> Result<Self, String> {
+    pub fn space(mut self, scale: Space, dim: i8) -> Result<Self, Error> {
         check_dim!(dim);
         self.pmapi_repr |= (scale as u32) << SPACE_SCALE_LSB;
         self.pmapi_repr |= ((dim as u32) & LS_FOUR_BIT_MASK) << SPACE_DIM_LSB;
@@ -380,7 +731,7 @@ impl Unit {
     }
 
     /// Modifies and returns the unit with given time scale and dimension
-    pub fn time(mut self, time: Time, dim: i8) -> Result<Self, String> {
+    pub fn time(mut self, time: Time, dim: i8) -> Result<Self, Error> {
         check_dim!(dim);
         self.pmapi_repr |= (time as u32) << TIME_SCALE_LSB;
         self.pmapi_repr |= ((dim as u32) & LS_FOUR_BIT_MASK) << TIME_DIM_LSB;
@@ -388,7 +739,7 @@ impl Unit {
     }
 
     /// Modifies and returns the unit with given count scale and dimension
-    pub fn count(mut self, count: Count, dim: i8) -> Result<Self, String> {
+    pub fn count(mut self, count: Count, dim: i8) -> Result<Self, Error> {
         check_dim!(dim);
         self.pmapi_repr |= (count as u32) << COUNT_SCALE_LSB;
         self.pmapi_repr |= ((dim as u32) & LS_FOUR_BIT_MASK) << COUNT_DIM_LSB;
@@ -523,43 +874,46 @@ impl fmt::Display for Semantics {
 pub struct Metric<T> {
     name: String,
     item: u32,
+    item_is_set: bool,
+    type_code: u32,
     sem: Semantics,
     indom: u32,
     unit: u32,
     shorthelp: String,
     longhelp: String,
-    val: T,
-    mmap_view: MmapViewSync
+    slot: Arc<Slot>,
+    phantom: PhantomData<T>
 }
 
 impl<T> AsMut<Metric<T>> for Metric<T> {
     fn as_mut(&mut self) -> &mut Metric<T> { self }
 }
 
-lazy_static! {
-    static ref SCRATCH_VIEW: MmapViewSync = {
-        Mmap::anonymous(STRING_BLOCK_LEN as usize, Protection::ReadWrite).unwrap()
-            .into_view_sync()
-    };
-}
-
 impl<T: MetricType + Clone> Metric<T> {
     /// Creates a new PCP MMV Metric
     ///
     /// The result is an error if the length of `name`, `shorthelp`
-    /// or `longhelp` exceed 255 bytes.
+    /// or `longhelp` exceed 255 bytes, or if `name` isn't a valid
+    /// PCP metric name.
+    ///
+    /// A valid name has one or more components separated by dots, where
+    /// each component begins with an ASCII letter followed by zero or more
+    /// ASCII letters, digits and underscores, e.g., `db.pool.active`.
     pub fn new(
         name: &str, init_val: T, sem: Semantics, unit: Unit, 
-        shorthelp: &str, longhelp: &str) -> Result<Self, String> {
+        shorthelp: &str, longhelp: &str) -> Result<Self, Error> {
         
         if name.len() >= STRING_BLOCK_LEN as usize {
-            return Err(format!("name longer than {} bytes", STRING_BLOCK_LEN - 1));
+            return Err(Error::NameTooLong(name.to_owned()));
+        }
+        if !is_valid_name(name) {
+            return Err(Error::InvalidName(name.to_owned()));
         }
         if shorthelp.len() >= STRING_BLOCK_LEN as usize {
-            return Err(format!("short help text longer than {} bytes", STRING_BLOCK_LEN - 1));
+            return Err(Error::HelpTextTooLong(shorthelp.to_owned()));
         }
         if longhelp.len() >= STRING_BLOCK_LEN as usize {
-            return Err(format!("long help text longer than {} bytes", STRING_BLOCK_LEN - 1));
+            return Err(Error::HelpTextTooLong(longhelp.to_owned()));
         }
 
         let mut hasher = DefaultHasher::new();
@@ -569,19 +923,38 @@ impl<T: MetricType + Clone> Metric<T> {
         Ok(Metric {
             name: name.to_owned(),
             item: item,
+            item_is_set: false,
+            type_code: init_val.type_code(),
             sem: sem,
             indom: 0,
             unit: unit.pmapi_repr,
             shorthelp: shorthelp.to_owned(),
             longhelp: longhelp.to_owned(),
-            val: init_val,
-            mmap_view: unsafe { SCRATCH_VIEW.clone() }
+            slot: Arc::new(new_slot(&init_val)?),
+            phantom: PhantomData
         })
     }
 
+    // returns a metric that shares this metric's value
+    fn clone_shared(&self) -> Self {
+        Metric {
+            name: self.name.clone(),
+            item: self.item,
+            item_is_set: self.item_is_set,
+            type_code: self.type_code,
+            sem: self.sem,
+            indom: self.indom,
+            unit: self.unit,
+            shorthelp: self.shorthelp.clone(),
+            longhelp: self.longhelp.clone(),
+            slot: self.slot.clone(),
+            phantom: PhantomData
+        }
+    }
+
     /// Returns the current value of the metric
     pub fn val(&self) -> T {
-        self.val.clone()
+        T::load(&self.slot)
     }    
 
     /// Sets the current value of the metric.
@@ -591,15 +964,62 @@ impl<T: MetricType + Clone> Metric<T> {
     ///
     /// If the metric isn't exported, this method will still
     /// succeed and update the value.
-    pub fn set_val(&mut self, new_val: T) -> io::Result<()> {
-        new_val.write(unsafe { &mut self.mmap_view.as_mut_slice() })?;
-        self.val = new_val;
-        Ok(())
+    ///
+    /// Numeric values are written atomically, so the metric
+    /// can be shared and updated across threads.
+    pub fn set_val(&self, new_val: T) -> Result<(), Error> {
+        Ok(new_val.store(&self.slot)?)
+    }
+
+    // atomically replaces the value with `f` applied to it,
+    // and returns the new value
+    fn update<F: FnMut(T) -> T>(&self, f: F) -> io::Result<T> {
+        T::update(&self.slot, f)
+    }
+
+    /// Clears the value of the metric, so that it's reported as
+    /// having no value available until it's set again.
+    ///
+    /// The cleared value is a sentinel: the minimum value for signed
+    /// integers, the maximum value for unsigned integers, NaN for floats,
+    /// and the empty string for strings. PCP only interprets it as
+    /// "no value available" if the client has the `SENTINEL` flag set.
+    ///
+    /// A value that's set to the sentinel, e.g., `u64::MAX` or an empty
+    /// string, likewise reads as having no value available. Counters and
+    /// gauges leave a cleared value unchanged when incremented or
+    /// decremented, and saturate short of the sentinel otherwise.
+    pub fn clear(&self) -> Result<(), Error> {
+        Ok(T::sentinel().store(&self.slot)?)
+    }
+
+    /// Checks if the metric has a value available, i.e., it's value
+    /// isn't the sentinel value
+    pub fn is_available(&self) -> bool {
+        !self.val().is_sentinel()
     }
     
+    /// Sets the item ID of the metric, which otherwise is derived
+    /// from a hash of the metric's name
+    ///
+    /// If the hashed item ID is taken by another metric in the MMV,
+    /// the next free one is used instead. An item ID that's set is
+    /// always used as it is.
+    ///
+    /// The item ID has to be set before the metric is exported. The
+    /// result is an error if `item` doesn't fit in 10 bits.
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        if item >= (1 << ITEM_BIT_LEN) {
+            return Err(Error::InvalidItem(item));
+        }
+        self.item = item;
+        self.item_is_set = true;
+        Ok(())
+    }
+
     pub fn name(&self) -> &str { &self.name }
     pub fn item(&self) -> u32 { self.item }
-    pub fn type_code(&self) -> u32 { self.val.type_code() }
+    pub fn type_code(&self) -> u32 { self.type_code }
     pub fn sem(&self) -> &Semantics { &self.sem }
     pub fn unit(&self) -> u32 { self.unit }
     pub fn indom(&self) -> u32 { self.indom }
@@ -607,13 +1027,27 @@ impl<T: MetricType + Clone> Metric<T> {
     pub fn longhelp(&self) -> &str { &self.longhelp }
 }
 
+// checks a name against the rules of the PCP namespace (PMNS)
+fn is_valid_name(name: &str) -> bool {
+    name.split('.').all(|component| {
+        let mut chars = component.chars();
+        match chars.next() {
+            Some(c) if c.is_ascii_alphabetic() =>
+                chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            _ => false
+        }
+    })
+}
+
 #[derive(Clone)]
 /// An instance domain is a set of instances
 pub struct Indom {
     instances: HashSet<String>,
     id: u32,
     shorthelp: String,
-    longhelp: String
+    longhelp: String,
+    // name of the metric the domain was created for, if any
+    owner: String
 }
 
 impl Indom {
@@ -621,27 +1055,40 @@ impl Indom {
     ///
     /// The result is an error if the length of any `instance`, `shorthelp`
     /// or `longhelp` exceed 255 bytes.
-    pub fn new(instances: &[&str], shorthelp: &str, longhelp: &str) -> Result<Self, String> {
+    pub fn new(instances: &[&str], shorthelp: &str, longhelp: &str) -> Result<Self, Error> {
+        Indom::new_owned("", instances, shorthelp, longhelp)
+    }
+
+    // creates a new instance domain for the metric named `owner`, whose
+    // ID differs from that of any other metric's domain with the same
+    // instances, e.g., the rates of two meters
+    fn new_owned(owner: &str, instances: &[&str], shorthelp: &str, longhelp: &str)
+        -> Result<Self, Error> {
+
         let mut hasher = DefaultHasher::new();
         instances.hash(&mut hasher);
+        if !owner.is_empty() {
+            owner.hash(&mut hasher);
+        }
 
         for instance in instances {
             if instance.len() >= STRING_BLOCK_LEN as usize {
-                return Err(format!("instance longer than {} bytes", STRING_BLOCK_LEN - 1));
+                return Err(Error::InstanceTooLong(instance.to_string()));
             }
         }
         if shorthelp.len() >= STRING_BLOCK_LEN as usize {
-            return Err(format!("short help text longer than {} bytes", STRING_BLOCK_LEN - 1));
+            return Err(Error::HelpTextTooLong(shorthelp.to_owned()));
         }
         if longhelp.len() >= STRING_BLOCK_LEN as usize {
-            return Err(format!("long help text longer than {} bytes", STRING_BLOCK_LEN - 1));
+            return Err(Error::HelpTextTooLong(longhelp.to_owned()));
         }
 
         Ok(Indom {
             instances: instances.into_iter().map(|inst| inst.to_string()).collect(),
             id: (hasher.finish() as u32) & ((1 << INDOM_BIT_LEN) - 1),
             shorthelp: shorthelp.to_owned(),
-            longhelp: longhelp.to_owned()
+            longhelp: longhelp.to_owned(),
+            owner: owner.to_owned()
         })
     }
 
@@ -664,6 +1111,22 @@ impl Indom {
     pub fn shorthelp(&self) -> &str { &self.shorthelp }
     pub fn longhelp(&self) -> &str { &self.longhelp }
 
+    // returns a copy of the domain with `instance` added or removed
+    //
+    // the ID is derived again, since an MMV identifies the set of
+    // instances in a domain by it's ID
+    fn with_instance(&self, instance: &str, present: bool) -> Result<Self, Error> {
+        let mut instances: Vec<&str> = self.instances.iter()
+            .map(|inst| inst.as_str())
+            .filter(|inst| *inst != instance)
+            .collect();
+        if present {
+            instances.push(instance);
+        }
+        instances.sort();
+        Indom::new_owned(&self.owner, &instances, &self.shorthelp, &self.longhelp)
+    }
+
     fn instance_id(instance: &str) -> u32 {
         let mut hasher = DefaultHasher::new();
         instance.hash(&mut hasher);
@@ -677,17 +1140,22 @@ impl Indom {
     }
 }
 
-struct Instance<T> {
-    val: T,
-    mmap_view: MmapViewSync
+// instances of an instance metric, which can change after it's exported
+struct Instances {
+    indom: Indom,
+    slots: HashMap<String, Arc<Slot>>,
+    export: Option<ExportLink>
 }
 
 /// An instance metric is a set of related metrics with same
 /// type, semantics and unit. Many instance metrics can share
 /// the same set of instances, i.e., instance domain.
+///
+/// Instances can be added and removed after the metric is exported,
+/// in which case the MMV is regenerated. The metric then gets it's own
+/// copy of the instance domain with a new ID.
 pub struct InstanceMetric<T> {
-    indom: Indom,
-    vals: HashMap<String, Instance<T>>,
+    instances: Arc<RwLock<Instances>>,
     metric: Metric<T>
 }
 
@@ -699,7 +1167,8 @@ impl<T: MetricType + Clone> InstanceMetric<T> {
     /// Creates a new instance metric
     ///
     /// The result is an error if the length of `name`, `shorthelp`
-    /// or `longhelp` exceed 255 bytes.
+    /// or `longhelp` exceed 255 bytes, or if `name` isn't a valid
+    /// PCP metric name, as with `Metric::new`.
     pub fn new(
         indom: &Indom,
         name: &str,
@@ -707,7 +1176,7 @@ impl<T: MetricType + Clone> InstanceMetric<T> {
         sem: Semantics,
         unit: Unit,
         shorthelp: &str,
-        longhelp: &str) -> Result<Self, String> {
+        longhelp: &str) -> Result<Self, Error> {
 
         let mut vals = HashMap::with_capacity(indom.instances.len());
         let mut metric_name = name.to_owned();
@@ -715,11 +1184,8 @@ impl<T: MetricType + Clone> InstanceMetric<T> {
         for instance_str in &indom.instances {
             metric_name.push_str(instance_str);
 
-            let instance = Instance {
-                val: init_val.clone(),
-                mmap_view: unsafe { SCRATCH_VIEW.clone() }
-            };
-            vals.insert(instance_str.to_owned(), instance);
+            let slot = new_slot(&init_val)?;
+            vals.insert(instance_str.to_owned(), Arc::new(slot));
 
             metric_name.truncate(name.len() + 1);
         }
@@ -730,37 +1196,156 @@ impl<T: MetricType + Clone> InstanceMetric<T> {
         metric.indom = indom.id;
         
         Ok(InstanceMetric {
-            indom: indom.clone(),
-            vals: vals,
+            instances: Arc::new(RwLock::new(Instances {
+                indom: indom.clone(),
+                slots: vals,
+                export: None
+            })),
             metric: metric
         })
     }
 
+    // returns an instance metric that shares this metric's instances
+    fn clone_shared(&self) -> Self {
+        InstanceMetric {
+            instances: self.instances.clone(),
+            metric: self.metric.clone_shared()
+        }
+    }
+
+    fn read_instances(&self) -> RwLockReadGuard<Instances> {
+        self.instances.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write_instances(&self) -> RwLockWriteGuard<Instances> {
+        self.instances.write().unwrap_or_else(|e| e.into_inner())
+    }
+
     /// Returns the number of instances that're part of the metric
     pub fn instance_count(&self) -> u32 {
-        self.vals.len() as u32
+        self.read_instances().slots.len() as u32
     }
 
     /// Check if given instance is part of the metric
     pub fn has_instance(&self, instance: &str) -> bool {
-        self.vals.contains_key(instance)
+        self.read_instances().slots.contains_key(instance)
+    }
+
+    /// Returns the current instance domain of the metric
+    pub fn indom(&self) -> Indom {
+        self.read_instances().indom.clone()
+    }
+
+    /// Sets the item ID of the metric, as with `Metric::set_item`
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        self.metric.set_item(item)
     }
 
     /// Returns the value of the given instance
     pub fn val(&self, instance: &str) -> Option<T> {
-        self.vals.get(instance).map(|i| i.val.clone())
+        self.read_instances().slots.get(instance).map(|slot| T::load(slot))
+    }
+
+    /// Sets the value of the given instance
+    ///
+    /// The result is an `UnknownInstance` error if the instance
+    /// isn't found.
+    ///
+    /// Numeric values are written atomically, so the metric
+    /// can be shared and updated across threads.
+    pub fn set_val(&self, instance: &str, new_val: T) -> Result<(), Error> {
+        match self.read_instances().slots.get(instance) {
+            Some(slot) => Ok(new_val.store(slot)?),
+            None => Err(Error::UnknownInstance(instance.to_owned()))
+        }
     }
 
-    /// Sets the value of the given instance. If the instance isn't
-    /// found, returns `None`.
-    pub fn set_val(&mut self, instance: &str, new_val: T) -> Option<io::Result<()>>  {
-        self.vals.get_mut(instance).map(|i| {
-            new_val.write(unsafe { &mut i.mmap_view.as_mut_slice() })?;
-            i.val = new_val;
+    // atomically replaces the value of the given instance with `f`
+    // applied to it, and returns the new value
+    fn update<F: FnMut(T) -> T>(&self, instance: &str, f: F) -> Result<T, Error> {
+        match self.read_instances().slots.get(instance) {
+            Some(slot) => Ok(T::update(slot, f)?),
+            None => Err(Error::UnknownInstance(instance.to_owned()))
+        }
+    }
+
+    /// Clears the value of the given instance, so that it's reported
+    /// as having no value available until it's set again. See
+    /// `Metric::clear` for the sentinel value that's written.
+    ///
+    /// The result is an `UnknownInstance` error if the instance
+    /// isn't found.
+    pub fn clear(&self, instance: &str) -> Result<(), Error> {
+        self.set_val(instance, T::sentinel())
+    }
+
+    /// Checks if the given instance has a value available. Returns
+    /// `false` if the instance isn't found.
+    pub fn is_available(&self, instance: &str) -> bool {
+        self.val(instance).map_or(false, |val| !val.is_sentinel())
+    }
+
+    // atomically applies `f` to the value of every instance
+    fn update_all<F: FnMut(T) -> T>(&self, mut f: F) -> io::Result<()> {
+        for slot in self.read_instances().slots.values() {
+            T::update(slot, &mut f)?;
+        }
+        Ok(())
+    }
+
+    /// Adds an instance with the given initial value. If the metric
+    /// is exported, the MMV is regenerated with the new instance and the
+    /// current values of all the metrics in it.
+    ///
+    /// The result is a `DuplicateInstance` error if the instance
+    /// already exists.
+    pub fn add_instance(&self, instance: &str, init_val: T) -> Result<(), Error> {
+        self.change_instances(|instances| {
+            if instances.slots.contains_key(instance) {
+                return Err(Error::DuplicateInstance(instance.to_owned()));
+            }
+            let indom = instances.indom.with_instance(instance, true)?;
+            instances.slots.insert(instance.to_owned(), Arc::new(new_slot(&init_val)?));
+            instances.indom = indom;
             Ok(())
         })
     }
 
+    /// Removes an instance. If the metric is exported, the MMV is
+    /// regenerated without the instance.
+    ///
+    /// The result is an `UnknownInstance` error if the instance
+    /// isn't found.
+    pub fn remove_instance(&self, instance: &str) -> Result<(), Error> {
+        self.change_instances(|instances| {
+            if !instances.slots.contains_key(instance) {
+                return Err(Error::UnknownInstance(instance.to_owned()));
+            }
+            let indom = instances.indom.with_instance(instance, false)?;
+            instances.slots.remove(instance);
+            instances.indom = indom;
+            Ok(())
+        })
+    }
+
+    // applies `f` to the instances and regenerates the MMV if it succeeds
+    fn change_instances<F>(&self, f: F) -> Result<(), Error>
+        where F: FnOnce(&mut Instances) -> Result<(), Error> {
+
+        // the lock on the instances has to be released before
+        // regenerating, since writing the metric needs it
+        let export = {
+            let mut instances = self.write_instances();
+            f(&mut instances)?;
+            instances.export.clone()
+        };
+
+        match export {
+            Some(export) => export.regenerate(),
+            None => Ok(())
+        }
+    }
+
     pub fn name(&self) -> &str { &self.metric.name }
     pub fn sem(&self) -> &Semantics { &self.metric.sem }
     pub fn unit(&self) -> u32 { self.metric.unit }
@@ -768,9 +1353,9 @@ impl<T: MetricType + Clone> InstanceMetric<T> {
     pub fn longhelp(&self) -> &str { &self.metric.longhelp }
 }
 
-impl<T: MetricType> Metric<T> {
-    fn write_to_mmv(&mut self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>,
-                 mmv_ver: Version, write_value_blk: bool) -> io::Result<u64> {
+impl<T: MetricType + Clone> Metric<T> {
+    fn write_to_mmv(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>,
+                 mmv_ver: Version, indom: u32, write_value_blk: bool) -> io::Result<u64> {
 
         let orig_pos = c.position();
 
@@ -798,15 +1383,16 @@ impl<T: MetricType> Metric<T> {
         }
 
         // item
-        c.write_u32::<Endian>(self.item)?;
+        let item = ws.metric_names.get(&self.name).cloned().unwrap_or(self.item);
+        c.write_u32::<Endian>(item)?;
         // type code
-        c.write_u32::<Endian>(self.val.type_code())?;
+        c.write_u32::<Endian>(self.type_code())?;
         // sem
         c.write_u32::<Endian>(self.sem as u32)?;
         // unit
         c.write_u32::<Endian>(self.unit)?;
         // indom
-        c.write_u32::<Endian>(self.indom)?;
+        c.write_u32::<Endian>(indom)?;
         // zero pad
         c.write_u32::<Endian>(0)?;
         // short help
@@ -818,35 +1404,60 @@ impl<T: MetricType> Metric<T> {
 
         if write_value_blk {
             let (value_offset, value_size) =
-                write_value_block(ws, c, &self.val, metric_blk_off, 0)?;
+                write_value_block(ws, c, &self.val(), metric_blk_off, 0)?;
 
             let mmap_view = unsafe {
                 ws.mmap_view.as_mut().unwrap().clone()
             };
             let (_, value_mmap_view, _) =
                 three_way_split(mmap_view, value_offset, value_size)?;
-            self.mmap_view = value_mmap_view;
+            self.slot.remap(value_mmap_view);
         }
 
         ws.metric_blk_idx += 1;
         c.set_position(orig_pos);
         Ok(metric_blk_off)
     }
+
+    // reserves the metric's name and item in the MMV, which
+    // would be ambiguous if shared with another metric
+    fn register_item(&self, ws: &mut MMVWriterState) -> Result<(), Error> {
+        if ws.metric_names.contains_key(&self.name) {
+            return Err(Error::DuplicateName(self.name.clone()));
+        }
+        if !self.item_is_set {
+            ws.hashed_items.push((self.name.clone(), self.item));
+            ws.metric_names.insert(self.name.clone(), self.item);
+            return Ok(());
+        }
+        if let Some(name) = ws.metric_items.get(&self.item) {
+            return Err(Error::DuplicateItem {
+                item: self.item,
+                name: self.name.clone(),
+                other_name: name.clone()
+            });
+        }
+        ws.metric_items.insert(self.item, self.name.clone());
+        ws.metric_names.insert(self.name.clone(), self.item);
+        Ok(())
+    }
 }
 
-impl<T: MetricType> MMVWriter for Metric<T> {
+impl<T: MetricType + Clone> MMVWriter for Metric<T> {
     private_impl!{}
 
-    fn write(&mut self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
-        self.write_to_mmv(ws, c, mmv_ver, true)?;
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+        self.write_to_mmv(ws, c, mmv_ver, self.indom, true)?;
         Ok(())
     }
 
-    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) {
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
+        self.register_item(ws)?;
+
         ws.n_metrics += 1;
         ws.n_values += 1;
 
-        if self.val.type_code() == MTCode::String as u32 {
+        if self.type_code() == MTCode::String as u32 {
             ws.n_strings += 1;
         }
 
@@ -857,78 +1468,143 @@ impl<T: MetricType> MMVWriter for Metric<T> {
             Version::V1 => {},
             Version::V2 => cache_and_register_string(ws, &self.name)
         }
+
+        Ok(())
     }
 
     fn has_mmv2_string(&self) -> bool {
         self.name.len() >= MMV1_NAME_MAX_LEN as usize
     }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        Box::new(self.clone_shared())
+    }
+
+    fn id(&self) -> usize {
+        &*self.slot as *const Slot as usize
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
 }
 
-impl<T: MetricType> MMVWriter for InstanceMetric<T> {
+impl<T: MetricType + Clone> MMVWriter for InstanceMetric<T> {
     private_impl!{}
 
-    fn write(&mut self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+        self.write_instances().export = ws.export.clone();
+
+        // instances added or removed since registering are written
+        // when the MMV is regenerated for them
+        let instances = match ws.instance_snapshots.remove(&self.id()) {
+            Some(instances) => instances,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("instance metric \"{}\" wasn't registered", self.metric.name)))
+        };
+
         // write metric block
-        let metric_blk_off = self.metric.write_to_mmv(ws, c, mmv_ver, false)?;
+        let metric_blk_off = self.metric.write_to_mmv(
+            ws, c, mmv_ver, instances.indom.id, false)?;
 
         // write indom and instances
-        let instance_blk_offs = write_indom_and_instances(ws, c, &self.indom, mmv_ver)?;
+        let instance_blk_offs = write_indom_and_instances(ws, c, &instances.indom, mmv_ver)?;
 
         // write value blocks
-        for ((_, instance), instance_blk_off) in self.vals.iter_mut().zip(instance_blk_offs) {
+        for &(ref instance, ref slot) in instances.slots.iter() {
+            let instance_blk_off = match instance_blk_offs.get(instance) {
+                Some(&instance_blk_off) => instance_blk_off,
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                    format!("instance \"{}\" isn't in it's domain", instance)))
+            };
 
             let (value_offset, value_size) =
-                write_value_block(ws, c, &instance.val, metric_blk_off, instance_blk_off)?;
+                write_value_block(ws, c, &T::load(slot), metric_blk_off, instance_blk_off)?;
 
-            // set mmap_view for instance
+            // re-point the instance's slot at it's value in the mmap
             let mmap_view = unsafe {
                 ws.mmap_view.as_mut().unwrap().clone()
             };
             let (_, value_mmap_view, _) =
                 three_way_split(mmap_view, value_offset, value_size)?;
-            instance.mmap_view = value_mmap_view;
+            slot.remap(value_mmap_view);
         }
 
         Ok(())
     }
 
-    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) {
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
+        self.metric.register_item(ws)?;
+
+        // the instances are snapshotted, so that the same ones are
+        // counted here and written later on
+        let instances = {
+            let instances = self.read_instances();
+            InstancesSnapshot {
+                indom: instances.indom.clone(),
+                slots: instances.slots.iter()
+                    .map(|(instance, slot)| (instance.clone(), slot.clone()))
+                    .collect()
+            }
+        };
+        let indom = &instances.indom;
+
         ws.n_metrics += 1;
-        ws.n_values += self.vals.len() as u64;
+        ws.n_values += instances.slots.len() as u64;
 
-        if self.metric.val.type_code() == MTCode::String as u32 {
-            ws.n_strings += 1;
+        // every instance's string value has it's own block
+        if self.metric.type_code() == MTCode::String as u32 {
+            ws.n_strings += instances.slots.len() as u64;
         }
 
         cache_and_register_string(ws, &self.metric.shorthelp);
         cache_and_register_string(ws, &self.metric.longhelp);
-        cache_and_register_string(ws, &self.indom.shorthelp);
-        cache_and_register_string(ws, &self.indom.longhelp);
+        cache_and_register_string(ws, &indom.shorthelp);
+        cache_and_register_string(ws, &indom.longhelp);
 
-        if !ws.indom_cache.contains_key(&self.indom.id) {
+        match mmv_ver {
+            Version::V1 => {},
+            Version::V2 => cache_and_register_string(ws, &self.metric.name)
+        }
+
+        if !ws.indom_cache.contains_key(&indom.id) {
             ws.n_indoms += 1;
-            ws.n_instances += self.indom.instances.len() as u64;
-            ws.indom_cache.insert(self.indom.id, None);
+            ws.n_instances += indom.instances.len() as u64;
+            ws.indom_cache.insert(indom.id, None);
 
             match mmv_ver {
                 Version::V1 => {},
                 Version::V2 => {
-                    cache_and_register_string(ws, &self.metric.name);
-                    for instance in &self.indom.instances {
+                    for instance in &indom.instances {
                         cache_and_register_string(ws, instance);
                     }
                 }
             }
         }
+
+        ws.instance_snapshots.insert(self.id(), instances);
+        Ok(())
     }
 
     fn has_mmv2_string(&self) -> bool {
-        self.metric.has_mmv2_string() || self.indom.has_mmv2_string()
+        self.metric.has_mmv2_string() || self.read_instances().indom.has_mmv2_string()
+    }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        Box::new(self.clone_shared())
+    }
+
+    fn id(&self) -> usize {
+        &*self.instances as *const RwLock<Instances> as usize
+    }
+
+    fn name(&self) -> &str {
+        &self.metric.name
     }
 }
 
 fn write_indom_and_instances<'a>(ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>,
-    indom: &Indom, mmv_ver: Version)-> io::Result<Vec<u64>> {
+    indom: &Indom, mmv_ver: Version)-> io::Result<HashMap<String, u64>> {
 
     // write each indom and it's instances only once
     if let Some(blk_offs) = ws.indom_cache.get(&indom.id) {
@@ -947,7 +1623,7 @@ fn write_indom_and_instances<'a>(ws: &mut MMVWriterState, c: &mut Cursor<&mut [u
     // number of instances
     c.write_u32::<Endian>(indom.instance_count())?;
 
-    // offset to instances
+    // offset to instances, or 0 if there are none
     let instance_blk_len = match mmv_ver {
         Version::V1 => INSTANCE_BLOCK_LEN_MMV1,
         Version::V2 => INSTANCE_BLOCK_LEN_MMV2
@@ -955,7 +1631,11 @@ fn write_indom_and_instances<'a>(ws: &mut MMVWriterState, c: &mut Cursor<&mut [u
     let mut instance_blk_off =
         ws.instance_sec_off
         + instance_blk_len*ws.instance_idx;
-    c.write_u64::<Endian>(instance_blk_off)?;
+    if indom.instance_count() > 0 {
+        c.write_u64::<Endian>(instance_blk_off)?;
+    } else {
+        c.write_u64::<Endian>(0)?;
+    }
 
     // short help
     let short_help_off = write_mmv_string(ws, c, indom.shorthelp(), false)?;
@@ -965,7 +1645,7 @@ fn write_indom_and_instances<'a>(ws: &mut MMVWriterState, c: &mut Cursor<&mut [u
     c.write_u64::<Endian>(long_help_off)?;
 
     // write instances and record their offsets
-    let mut instance_blk_offs = Vec::with_capacity(indom.instances.len());
+    let mut instance_blk_offs = HashMap::with_capacity(indom.instances.len());
     for instance in &indom.instances {
         c.set_position(instance_blk_off);
 
@@ -988,7 +1668,7 @@ fn write_indom_and_instances<'a>(ws: &mut MMVWriterState, c: &mut Cursor<&mut [u
             }
         }
 
-        instance_blk_offs.push(instance_blk_off);
+        instance_blk_offs.insert(instance.to_owned(), instance_blk_off);
         instance_blk_off += instance_blk_len;
     }
 
@@ -1000,6 +1680,84 @@ fn write_indom_and_instances<'a>(ws: &mut MMVWriterState, c: &mut Cursor<&mut [u
     Ok(cloned_offs)
 }
 
+// writers of the metrics that make up a single compound metric, the
+// first of which identifies and names it
+struct CompoundWriter(Vec<Box<MMVWriter + Send + Sync>>);
+
+impl MMVWriter for CompoundWriter {
+    private_impl!{}
+
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+        for writer in self.0.iter() {
+            writer.write(ws, c, mmv_ver)?;
+        }
+        Ok(())
+    }
+
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
+        for writer in self.0.iter() {
+            writer.register(ws, mmv_ver)?;
+        }
+        Ok(())
+    }
+
+    fn has_mmv2_string(&self) -> bool {
+        self.0.iter().any(|writer| writer.has_mmv2_string())
+    }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        Box::new(CompoundWriter(self.0.iter().map(|writer| writer.share()).collect()))
+    }
+
+    fn id(&self) -> usize {
+        self.0[0].id()
+    }
+
+    fn name(&self) -> &str {
+        self.0[0].name()
+    }
+
+    fn sample(&self) -> Result<(), Error> {
+        sample_writers(&self.0)
+    }
+}
+
+// assigns items to the registered metrics that don't have one set,
+// using the hashed item or the next free one after it, in the order
+// the metrics were registered
+pub (crate) fn assign_items(ws: &mut MMVWriterState) -> Result<(), Error> {
+    let n_items = 1 << ITEM_BIT_LEN;
+    for (name, hashed_item) in mem::replace(&mut ws.hashed_items, Vec::new()) {
+        let item = match (0..n_items)
+            .map(|i| (hashed_item + i) % n_items)
+            .find(|item| !ws.metric_items.contains_key(item)) {
+            Some(item) => item,
+            None => return Err(Error::DuplicateItem {
+                item: hashed_item,
+                name: name,
+                other_name: ws.metric_items[&hashed_item].clone()
+            })
+        };
+        ws.metric_items.insert(item, name.clone());
+        ws.metric_names.insert(name, item);
+    }
+    Ok(())
+}
+
+// samples every writer, even if sampling some of them fails, and
+// returns the first error
+pub (crate) fn sample_writers(writers: &[Box<MMVWriter + Send + Sync>]) -> Result<(), Error> {
+    let mut result = Ok(());
+    for writer in writers.iter() {
+        if let Err(err) = writer.sample() {
+            if result.is_ok() {
+                result = Err(err);
+            }
+        }
+    }
+    result
+}
+
 fn three_way_split(view: MmapViewSync, mid_idx: usize, mid_len: usize) -> io::Result<(MmapViewSync, MmapViewSync, MmapViewSync)> {
     let (left_view, mid_right_view) = view.split_at(mid_idx).unwrap();
     let (mid_view, right_view) = mid_right_view.split_at(mid_len).unwrap();
@@ -1145,10 +1903,158 @@ fn test_instance_metrics() {
     Client::new("system").unwrap()
         .export(&mut [&mut cache_sizes, &mut cpu]).unwrap();
 
-    assert!(cache_sizes.set_val("L3", 8192).is_some());
+    assert!(cache_sizes.set_val("L3", 8192).is_ok());
     assert_eq!(cache_sizes.val("L3").unwrap(), 8192);
     
-    assert!(cache_sizes.set_val("L4", 16384).is_none());
+    match cache_sizes.set_val("L4", 16384) {
+        Err(Error::UnknownInstance(ref instance)) => assert_eq!(instance, "L4"),
+        _ => panic!("L4 shouldn't be an instance")
+    }
+}
+
+#[test]
+fn test_sentinel_values() {
+    use super::super::mmv::dump;
+    use super::{Client, PROCESS, SENTINEL};
+
+    let sem = Semantics::Instant;
+    let unit = Unit::new();
+
+    let mut metric = Metric::new("sentinel_metric", 1.5f64, sem, unit, "", "").unwrap();
+    let indom = Indom::new(&["a", "b"], "", "").unwrap();
+    let mut im = InstanceMetric::new(&indom, "sentinel_im", 1u32, sem, unit, "", "").unwrap();
+
+    let client = Client::new_custom("sentinel_values", PROCESS | SENTINEL, 0).unwrap();
+    client.export(&mut [&mut metric, &mut im]).unwrap();
+
+    assert!(metric.is_available());
+    metric.clear().unwrap();
+    assert!(!metric.is_available());
+    assert!(metric.val().is_nan());
+
+    im.clear("b").unwrap();
+    assert!(im.is_available("a"));
+    assert!(!im.is_available("b"));
+    assert!(!im.is_available("c"));
+    assert!(im.clear("c").is_err());
+
+    let mmv = dump(client.mmv_path()).unwrap();
+    let vals: Vec<u64> = mmv.value_blks().values().map(|v| v.value()).collect();
+    assert!(vals.contains(&unsafe { mem::transmute::<f64, u64>(::std::f64::NAN) }));
+    assert!(vals.contains(&(::std::u32::MAX as u64)));
+    assert!(vals.contains(&1));
+
+    metric.set_val(2.5).unwrap();
+    im.set_val("b", 2).unwrap();
+    assert!(metric.is_available());
+    assert!(im.is_available("b"));
+}
+
+#[test]
+fn test_add_remove_instances() {
+    use super::Client;
+    use super::super::mmv::dump;
+
+    let indom = Indom::new(&["a", "b"], "", "").unwrap();
+    let mut im = InstanceMetric::new(
+        &indom, "add_remove_instances", 0, Semantics::Instant, Unit::new(), "", ""
+    ).unwrap();
+    let mut metric = Metric::new(
+        "add_remove_singleton", 0, Semantics::Instant, Unit::new(), "", ""
+    ).unwrap();
+
+    let client = Client::new("add_remove_instances").unwrap();
+    client.export(&mut [&mut im, &mut metric]).unwrap();
+    im.set_val("a", 1).unwrap();
+    metric.set_val(2).unwrap();
+
+    let gen = dump(client.mmv_path()).unwrap().header().gen1();
+
+    im.add_instance("c", 3).unwrap();
+    assert!(im.add_instance("c", 3).is_err());
+    assert_eq!(im.instance_count(), 3);
+    assert!(im.indom().id != indom.id);
+
+    // existing values are carried over, and every metric writes
+    // to the regenerated MMV
+    metric.set_val(4).unwrap();
+    im.set_val("b", 5).unwrap();
+
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert!(mmv.header().gen1() > gen);
+    assert_eq!(mmv.instance_blks().len(), 3);
+    let mut vals: Vec<u64> = mmv.value_blks().values().map(|v| v.value()).collect();
+    vals.sort();
+    assert_eq!(vals, vec![1, 3, 4, 5]);
+
+    im.remove_instance("a").unwrap();
+    assert!(im.remove_instance("a").is_err());
+    assert!(im.val("a").is_none());
+
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert_eq!(mmv.instance_blks().len(), 2);
+    let mut vals: Vec<u64> = mmv.value_blks().values().map(|v| v.value()).collect();
+    vals.sort();
+    assert_eq!(vals, vec![3, 4, 5]);
+}
+
+#[test]
+fn test_instances_changed_while_exporting() {
+    use super::Client;
+    use super::super::mmv::dump;
+
+    // adds an instance to an instance metric once it's been registered,
+    // as another thread could while the MMV is being exported
+    struct AddInstance(InstanceMetric<u32>, Metric<u32>);
+
+    impl MMVWriter for AddInstance {
+        private_impl!{}
+
+        fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+            self.1.write(ws, c, mmv_ver)
+        }
+
+        fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
+            if !self.0.has_instance("c") {
+                self.0.add_instance("c", 3)?;
+            }
+            self.1.register(ws, mmv_ver)
+        }
+
+        fn has_mmv2_string(&self) -> bool { false }
+
+        fn share(&self) -> Box<MMVWriter + Send + Sync> {
+            Box::new(AddInstance(self.0.clone_shared(), self.1.clone_shared()))
+        }
+
+        fn id(&self) -> usize { self.1.id() }
+
+        fn name(&self) -> &str { self.1.name() }
+    }
+
+    let indom = Indom::new(&["a", "b"], "", "").unwrap();
+    let mut im = InstanceMetric::new(
+        &indom, "changed_instances", 1u32, Semantics::Instant, Unit::new(), "", ""
+    ).unwrap();
+    let mut adder = AddInstance(
+        im.clone_shared(),
+        Metric::new("changed_instances_adder", 2u32, Semantics::Instant, Unit::new(), "", "").unwrap()
+    );
+
+    let client = Client::new("changed_instances_test").unwrap();
+    client.export(&mut [&mut im, &mut adder]).unwrap();
+
+    // the MMV holds the instances that were registered
+    assert_eq!(im.instance_count(), 3);
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert_eq!(mmv.instance_blks().len(), 2);
+    assert_eq!(mmv.value_blks().len(), 3);
+
+    // and the added instance is written once it's regenerated
+    im.remove_instance("a").unwrap();
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert_eq!(mmv.instance_blks().len(), 2);
+    assert_eq!(im.val("c"), Some(3));
 }
 
 #[test]
@@ -1201,10 +2107,10 @@ fn test_invalid_strings() {
         &invalid_string, 0, sem, unit, "", ""
     ).is_err());
     assert!(Metric::new(
-        "", 0, sem, unit, &invalid_string, ""
+        "m", 0, sem, unit, &invalid_string, ""
     ).is_err());
     assert!(Metric::new(
-        "", 0, sem, unit, "", &invalid_string
+        "m", 0, sem, unit, "", &invalid_string
     ).is_err());
 
     assert!(Indom::new(
@@ -1222,13 +2128,30 @@ fn test_invalid_strings() {
         &indom, &invalid_string, 0, sem, unit, "", ""
     ).is_err());
     assert!(InstanceMetric::new(
-        &indom, "", 0, sem, unit, &invalid_string, ""
+        &indom, "m", 0, sem, unit, &invalid_string, ""
     ).is_err());
     assert!(InstanceMetric::new(
-        &indom, "", 0, sem, unit, "", &invalid_string
+        &indom, "m", 0, sem, unit, "", &invalid_string
     ).is_err());
 }
 
+#[test]
+fn test_invalid_names() {
+    let sem = Semantics::Discrete;
+    let unit = Unit::new();
+
+    for name in &["", "1m", "m.", ".m", "m..m", "m m", "m-m", "m.1m", "_m"] {
+        match Metric::new(name, 0, sem, unit, "", "") {
+            Err(Error::InvalidName(ref invalid_name)) => assert_eq!(invalid_name, name),
+            _ => panic!("\"{}\" should be an invalid name", name)
+        }
+    }
+
+    for name in &["m", "M1", "m_1", "db.pool.active", "a.b_c.D9"] {
+        assert!(Metric::new(name, 0, sem, unit, "", "").is_ok());
+    }
+}
+
 #[test]
 fn test_mmv2_string_check() {
     use rand::{thread_rng, Rng};
@@ -1236,9 +2159,10 @@ fn test_mmv2_string_check() {
     let sem = Semantics::Discrete;
     let unit = Unit::new();
 
-    let mmv1_string: String = thread_rng().gen_ascii_chars()
+    // names have to begin with a letter
+    let mmv1_string: String = Some('m').into_iter().chain(thread_rng().gen_ascii_chars())
         .take((MMV1_NAME_MAX_LEN - 1) as usize).collect();
-    let mmv2_string: String = thread_rng().gen_ascii_chars()
+    let mmv2_string: String = Some('m').into_iter().chain(thread_rng().gen_ascii_chars())
         .take((STRING_BLOCK_LEN - 1) as usize).collect();
 
     let mmv1_metric = Metric::new(&mmv1_string, 0, sem, unit, "", "").unwrap();
@@ -1270,12 +2194,16 @@ fn test_mmv2_string_blocks() {
     let sem = Semantics::Discrete;
     let unit = Unit::new();
 
-    let mmv2_string: String = thread_rng().gen_ascii_chars()
+    // names have to begin with a letter
+    let mmv2_string: String = Some('m').into_iter().chain(thread_rng().gen_ascii_chars())
         .take((STRING_BLOCK_LEN - 1) as usize).collect();
 
+    // metric names have to be unique within an MMV
+    let im_name = format!("i{}", &mmv2_string[1..]);
+
     let mut metric = Metric::new(&mmv2_string, 0, sem, unit, "", "").unwrap();
     let indom = Indom::new(&[&mmv2_string], "", "").unwrap();
-    let mut im = InstanceMetric::new(&indom, &mmv2_string, 0, sem, unit, "", "").unwrap();
+    let mut im = InstanceMetric::new(&indom, &im_name, 0, sem, unit, "", "").unwrap();
 
     let client = Client::new("mmv2_string_blocks").unwrap();
     client.export(&mut [&mut metric, &mut im]).unwrap();
@@ -1288,7 +2216,7 @@ fn test_mmv2_string_blocks() {
                 panic!("metric name \"{}\" should be in string section", s),
             &VersionSpecificString::Offset(ref off) => {
                 let string = mmv.string_blks().get(off).unwrap().string();
-                assert_eq!(string, mmv2_string);
+                assert!(*string == mmv2_string || *string == im_name);
             }
         }
     }
@@ -1317,8 +2245,9 @@ fn test_random_numeric_metrics() {
 
     let client = Client::new("numeric_metrics").unwrap();
     
-    for _ in 1..n_metrics {
-        let rnd_name: String = thread_rng().gen_ascii_chars()
+    for i in 1..n_metrics {
+        // names have to begin with a letter
+        let rnd_name: String = Some('m').into_iter().chain(thread_rng().gen_ascii_chars())
             .take(MMV1_NAME_MAX_LEN as usize - 1).collect();
 
         let rnd_shorthelp: String = thread_rng().gen_ascii_chars()
@@ -1338,6 +2267,8 @@ fn test_random_numeric_metrics() {
             &rnd_shorthelp,
             &rnd_longhelp,
         ).unwrap();
+        // hashed item IDs of random names may collide
+        metric.set_item(i as u32).unwrap();
 
         assert_eq!(metric.val(), rnd_val1);
 
@@ -1363,8 +2294,10 @@ fn test_random_numeric_metrics() {
     }
 
     for (m, v) in metrics.iter_mut().zip(new_vals) {
-        let mut slice = unsafe { m.mmap_view.as_slice() };
-        assert_eq!(v, slice.read_u64::<super::Endian>().unwrap() as u32);
+        let mmv_val = m.slot.with_bytes(|mut slice|
+            slice.read_u64::<super::Endian>().unwrap() as u32
+        );
+        assert_eq!(v, mmv_val);
     }
 }
 
@@ -1417,24 +2350,27 @@ fn test_simple_metrics() {
     let new_photon_count = thread_rng().gen::<u32>();
     assert!(photons.set_val(new_photon_count).is_ok());
 
-    let mut freq_slice = unsafe { freq.mmap_view.as_slice() };
-    assert_eq!(
-        new_freq,
-        unsafe { 
-            transmute::<u64, f64>(freq_slice.read_u64::<super::Endian>().unwrap())
-        }
+    freq.slot.with_bytes(|mut freq_slice|
+        assert_eq!(
+            new_freq,
+            unsafe { 
+                transmute::<u64, f64>(freq_slice.read_u64::<super::Endian>().unwrap())
+            }
+        )
     );
 
-    let color_slice = unsafe { color.mmap_view.as_slice() };
-    let cstr = unsafe {
-        CStr::from_ptr(color_slice.as_ptr() as *const i8)
-    };
-    assert_eq!(new_color, cstr.to_str().unwrap());
+    color.slot.with_bytes(|color_slice| {
+        let cstr = unsafe {
+            CStr::from_ptr(color_slice.as_ptr() as *const i8)
+        };
+        assert_eq!(new_color, cstr.to_str().unwrap());
+    });
 
-    let mut photon_slice = unsafe { photons.mmap_view.as_slice() };
-    assert_eq!(
-        new_photon_count,
-        photon_slice.read_u64::<super::Endian>().unwrap() as u32
+    photons.slot.with_bytes(|mut photon_slice|
+        assert_eq!(
+            new_photon_count,
+            photon_slice.read_u64::<super::Endian>().unwrap() as u32
+        )
     );
 
     // TODO: after implementing mmvdump functionality, test the
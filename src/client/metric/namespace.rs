@@ -0,0 +1,68 @@
+use super::*;
+
+/// A prefix shared by the names of related metrics
+///
+/// Names are built as a dotted hierarchy below the prefix, e.g., a
+/// `db.pool` namespace names it's metrics `db.pool.active`,
+/// `db.pool.idle`, and so on.
+#[derive(Clone)]
+pub struct Namespace {
+    prefix: String
+}
+
+impl Namespace {
+    /// Creates a namespace with the given prefix
+    ///
+    /// The result is an `InvalidName` error if the prefix isn't a
+    /// valid PCP metric name.
+    pub fn new(prefix: &str) -> Result<Self, Error> {
+        if !is_valid_name(prefix) {
+            return Err(Error::InvalidName(prefix.to_owned()));
+        }
+        Ok(Namespace {
+            prefix: prefix.to_owned()
+        })
+    }
+
+    /// Creates a namespace nested under this one
+    ///
+    /// The result is an `InvalidName` error if the resulting prefix
+    /// isn't a valid PCP metric name.
+    pub fn namespace(&self, prefix: &str) -> Result<Namespace, Error> {
+        Namespace::new(&self.name(prefix))
+    }
+
+    /// Returns the full name of a metric in the namespace
+    ///
+    /// The name isn't validated until a metric is created with it.
+    pub fn name(&self, name: &str) -> String {
+        format!("{}.{}", self.prefix, name)
+    }
+
+    /// Returns a builder for a metric in the namespace, with given
+    /// name and initial value
+    pub fn metric<T: MetricType + Clone>(&self, name: &str, init_val: T) -> MetricBuilder<T> {
+        MetricBuilder::new(&self.name(name), init_val)
+    }
+
+    pub fn prefix(&self) -> &str { &self.prefix }
+}
+
+#[test]
+fn test() {
+    let db = Namespace::new("db").unwrap();
+    let pool = db.namespace("pool").unwrap();
+    assert_eq!(pool.prefix(), "db.pool");
+    assert_eq!(pool.name("active"), "db.pool.active");
+
+    let idle = pool.metric("idle", 0u32).build().unwrap();
+    assert_eq!(idle.name(), "db.pool.idle");
+    assert!(pool.metric("1dle", 0u32).build().is_err());
+
+    assert!(Namespace::new("").is_err());
+    assert!(Namespace::new("db.").is_err());
+    assert!(db.namespace("pool size").is_err());
+
+    let counter = Counter::new(&pool.name("acquired"), 0, "", "").unwrap();
+    assert_eq!(counter.val(), 0);
+}
@@ -0,0 +1,309 @@
+use std::fs::{self, File};
+use std::io::Read;
+use super::*;
+
+/// A set of metrics describing the health of the current process
+///
+/// Exports the following metrics under a given prefix, e.g.,
+/// `myapp.process.rss`:
+///
+/// - `cpu_user` and `cpu_sys`: CPU time spent in user and kernel
+///   mode, in milliseconds, with `Semantics::Counter`
+/// - `rss` and `vsize`: resident and virtual memory size, in kilobytes
+/// - `fds`: number of open file descriptors
+/// - `threads`: number of threads
+/// - `uptime`: time since the process started, in seconds
+///
+/// The values are read from `/proc/self` when calling `refresh`, or when
+/// the metrics are sampled by a client, e.g., periodically with
+/// `Client::sample_every`, and so are only available on Linux.
+pub struct ProcessMetrics {
+    cpu_user: Metric<u64>,
+    cpu_sys: Metric<u64>,
+    rss: Metric<u64>,
+    vsize: Metric<u64>,
+    fds: Metric<u64>,
+    threads: Metric<u64>,
+    uptime: Metric<u64>
+}
+
+// returns the clock ticks per second that times in /proc are reported in
+#[cfg(unix)]
+fn user_hz() -> u64 {
+    use nix::libc::{sysconf, _SC_CLK_TCK};
+
+    match unsafe { sysconf(_SC_CLK_TCK) } {
+        hz if hz > 0 => hz as u64,
+        _ => DEFAULT_USER_HZ
+    }
+}
+
+#[cfg(not(unix))]
+fn user_hz() -> u64 {
+    DEFAULT_USER_HZ
+}
+
+// USER_HZ on most architectures, if it can't be queried
+const DEFAULT_USER_HZ: u64 = 100;
+
+// values parsed from /proc/self/stat, in clock ticks
+struct Stat {
+    utime: u64,
+    stime: u64,
+    starttime: u64
+}
+
+// values parsed from /proc/self/status
+struct Status {
+    rss_kb: u64,
+    vsize_kb: u64,
+    threads: u64
+}
+
+impl ProcessMetrics {
+    /// Creates the process metrics under the given prefix, and
+    /// reads their initial values
+    pub fn new(prefix: &str) -> Result<Self, Error> {
+        let ns = Namespace::new(prefix)?;
+        let msec = Unit::new().time(Time::MSec, 1)?;
+        let kbyte = Unit::new().space(Space::KByte, 1)?;
+        let count = Unit::new().count(Count::One, 1)?;
+        let sec = Unit::new().time(Time::Sec, 1)?;
+
+        let process_metrics = ProcessMetrics {
+            cpu_user: process_metric(&ns, "cpu_user", Semantics::Counter, msec,
+                "CPU time spent in user mode")?,
+            cpu_sys: process_metric(&ns, "cpu_sys", Semantics::Counter, msec,
+                "CPU time spent in kernel mode")?,
+            rss: process_metric(&ns, "rss", Semantics::Instant, kbyte,
+                "Resident memory size")?,
+            vsize: process_metric(&ns, "vsize", Semantics::Instant, kbyte,
+                "Virtual memory size")?,
+            fds: process_metric(&ns, "fds", Semantics::Instant, count,
+                "Number of open file descriptors")?,
+            threads: process_metric(&ns, "threads", Semantics::Instant, count,
+                "Number of threads")?,
+            uptime: process_metric(&ns, "uptime", Semantics::Instant, sec,
+                "Time since the process started")?
+        };
+        process_metrics.refresh()?;
+        Ok(process_metrics)
+    }
+
+    /// Reads the current values from `/proc/self` and updates the metrics
+    pub fn refresh(&self) -> Result<(), Error> {
+        let stat = parse_stat(&read_file("/proc/self/stat")?)?;
+        let status = parse_status(&read_file("/proc/self/status")?)?;
+        // the directory's own fd is listed while it's being read
+        let fds = fs::read_dir("/proc/self/fd")?.count().saturating_sub(1) as u64;
+        let system_uptime = parse_system_uptime(&read_file("/proc/uptime")?)?;
+        let user_hz = user_hz();
+
+        self.cpu_user.set_val(stat.utime * 1000 / user_hz)?;
+        self.cpu_sys.set_val(stat.stime * 1000 / user_hz)?;
+        self.rss.set_val(status.rss_kb)?;
+        self.vsize.set_val(status.vsize_kb)?;
+        self.fds.set_val(fds)?;
+        self.threads.set_val(status.threads)?;
+        self.uptime.set_val(system_uptime.saturating_sub(stat.starttime / user_hz))?;
+        Ok(())
+    }
+
+    /// CPU time spent in user mode, in milliseconds
+    pub fn cpu_user(&self) -> u64 { self.cpu_user.val() }
+    /// CPU time spent in kernel mode, in milliseconds
+    pub fn cpu_sys(&self) -> u64 { self.cpu_sys.val() }
+    /// Resident memory size, in kilobytes
+    pub fn rss(&self) -> u64 { self.rss.val() }
+    /// Virtual memory size, in kilobytes
+    pub fn vsize(&self) -> u64 { self.vsize.val() }
+    /// Number of open file descriptors
+    pub fn fds(&self) -> u64 { self.fds.val() }
+    /// Number of threads
+    pub fn threads(&self) -> u64 { self.threads.val() }
+    /// Time since the process started, in seconds
+    pub fn uptime(&self) -> u64 { self.uptime.val() }
+
+    fn metrics(&self) -> [&Metric<u64>; 7] {
+        [
+            &self.cpu_user, &self.cpu_sys, &self.rss, &self.vsize,
+            &self.fds, &self.threads, &self.uptime
+        ]
+    }
+}
+
+/// Returns the time the process with the given PID started at, in
+/// seconds since the epoch, or `None` if there's no such process
+pub (crate) fn process_start_time(pid: i32) -> io::Result<Option<i64>> {
+    let stat = match read_file(&format!("/proc/{}/stat", pid)) {
+        Ok(stat) => parse_stat(&stat)?,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err)
+    };
+    let boot_time = parse_boot_time(&read_file("/proc/stat")?)?;
+    Ok(Some((boot_time + stat.starttime / user_hz()) as i64))
+}
+
+fn process_metric(ns: &Namespace, name: &str, sem: Semantics, unit: Unit, help: &str)
+    -> Result<Metric<u64>, Error> {
+    ns.metric(name, 0)
+        .semantics(sem)
+        .unit(unit)
+        .shorthelp(help)
+        .build()
+}
+
+fn read_file(path: &str) -> io::Result<String> {
+    let mut contents = String::new();
+    File::open(path)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+fn invalid_data(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("couldn't parse {}", what))
+}
+
+fn parse_stat(stat: &str) -> io::Result<Stat> {
+    // the command name is in parentheses and may contain spaces,
+    // so fields are counted from after it, starting at field 3
+    let fields_start = stat.rfind(')').ok_or_else(|| invalid_data("/proc/self/stat"))?;
+    let fields: Vec<&str> = stat[fields_start + 1..].split_whitespace().collect();
+    let field = |n: usize| -> io::Result<u64> {
+        fields.get(n - 3)
+            .and_then(|field| field.parse().ok())
+            .ok_or_else(|| invalid_data("/proc/self/stat"))
+    };
+
+    Ok(Stat {
+        utime: field(14)?,
+        stime: field(15)?,
+        starttime: field(22)?
+    })
+}
+
+fn parse_status(status: &str) -> io::Result<Status> {
+    let field = |name: &str| -> io::Result<u64> {
+        status.lines()
+            .find(|line| line.starts_with(name) && line[name.len()..].starts_with(':'))
+            .and_then(|line| line[name.len() + 1..].split_whitespace().next())
+            .and_then(|val| val.parse().ok())
+            .ok_or_else(|| invalid_data("/proc/self/status"))
+    };
+
+    Ok(Status {
+        rss_kb: field("VmRSS")?,
+        vsize_kb: field("VmSize")?,
+        threads: field("Threads")?
+    })
+}
+
+fn parse_system_uptime(uptime: &str) -> io::Result<u64> {
+    uptime.split(|c: char| c == '.' || c.is_whitespace()).next()
+        .and_then(|secs| secs.parse().ok())
+        .ok_or_else(|| invalid_data("/proc/uptime"))
+}
+
+fn parse_boot_time(stat: &str) -> io::Result<u64> {
+    stat.lines()
+        .find(|line| line.starts_with("btime "))
+        .and_then(|line| line["btime ".len()..].trim().parse().ok())
+        .ok_or_else(|| invalid_data("/proc/stat"))
+}
+
+impl MMVWriter for ProcessMetrics {
+    private_impl!{}
+
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+        for m in self.metrics().iter() {
+            m.write(ws, c, mmv_ver)?;
+        }
+        Ok(())
+    }
+
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
+        for m in self.metrics().iter() {
+            m.register(ws, mmv_ver)?;
+        }
+        Ok(())
+    }
+
+    fn has_mmv2_string(&self) -> bool {
+        self.metrics().iter().any(|m| m.has_mmv2_string())
+    }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        Box::new(ProcessMetrics {
+            cpu_user: self.cpu_user.clone_shared(),
+            cpu_sys: self.cpu_sys.clone_shared(),
+            rss: self.rss.clone_shared(),
+            vsize: self.vsize.clone_shared(),
+            fds: self.fds.clone_shared(),
+            threads: self.threads.clone_shared(),
+            uptime: self.uptime.clone_shared()
+        })
+    }
+
+    fn id(&self) -> usize {
+        self.cpu_user.id()
+    }
+
+    fn name(&self) -> &str {
+        self.cpu_user.name()
+    }
+
+    fn sample(&self) -> Result<(), Error> {
+        self.refresh()
+    }
+}
+
+#[test]
+pub fn test_parse() {
+    let stat = parse_stat(
+        "1234 (my (odd) app) S 1 1234 1234 0 -1 4194304 500 0 0 0 \
+         250 120 0 0 20 0 3 0 98765 10485760 512 18446744073709551615"
+    ).unwrap();
+    assert_eq!(stat.utime, 250);
+    assert_eq!(stat.stime, 120);
+    assert_eq!(stat.starttime, 98765);
+    assert!(parse_stat("1234 (app) S 1").is_err());
+
+    let status = parse_status(
+        "Name:\tapp\nVmSize:\t   10240 kB\nVmRSS:\t    2048 kB\nThreads:\t3\n"
+    ).unwrap();
+    assert_eq!(status.vsize_kb, 10240);
+    assert_eq!(status.rss_kb, 2048);
+    assert_eq!(status.threads, 3);
+    assert!(parse_status("Name:\tapp\n").is_err());
+
+    assert_eq!(parse_system_uptime("3600.52 7000.10\n").unwrap(), 3600);
+
+    assert_eq!(parse_boot_time("cpu  1 2 3\nbtime 1500000000\nprocesses 42\n").unwrap(), 1500000000);
+    assert!(parse_boot_time("cpu  1 2 3\n").is_err());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+pub fn test() {
+    use super::super::{get_process_id, Client};
+    use time;
+
+    let mut process_metrics = ProcessMetrics::new("process_test").unwrap();
+    let client = Client::new("process_metrics_test").unwrap();
+    client.export(&mut [&mut process_metrics]).unwrap();
+
+    process_metrics.refresh().unwrap();
+    assert!(process_metrics.rss() > 0);
+    assert!(process_metrics.vsize() >= process_metrics.rss());
+    assert!(process_metrics.fds() > 0);
+    assert!(process_metrics.threads() > 0);
+
+    let start_time = process_start_time(get_process_id()).unwrap().unwrap();
+    assert!(start_time <= time::now().to_timespec().sec);
+
+    // sampling refreshes the metrics
+    process_metrics.uptime.set_val(::std::u64::MAX - 1).unwrap();
+    client.sample().unwrap();
+    assert!(process_metrics.uptime() < ::std::u64::MAX - 1);
+
+    assert!(user_hz() > 0);
+}
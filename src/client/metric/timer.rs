@@ -1,37 +1,34 @@
 use super::*;
-use time;
-use time::Tm;
+use std::time::{Duration, Instant};
 
 /// A timer metric for tracking elapsed time
 ///
 /// Internally uses a `Metric<i64>` with `Semantics::Instant` and `1` time dimension
+///
+/// Time is measured with a monotonic clock, either between a `start` and
+/// `stop` pair, or over the lifetime of a guard returned from `time`.
+/// Guards only need a shared reference, so many timings can overlap and
+/// add into the same cumulative value, e.g., with an `Arc<Timer>` shared
+/// between request threads.
 pub struct Timer {
     metric: Metric<i64>,
     time_scale: Time,
-    start_time: Option<Tm>
-}
-
-/// Error encountered while starting or stopping a timer
-#[derive(Debug)]
-pub enum Error {
-    /// IO error
-    Io(io::Error),
-    /// Timer was already started
-    TimerAlreadyStarted,
-    /// Timer wasn't previously started
-    TimerNotStarted,
+    start_time: Option<Instant>
 }
 
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Error {
-        Error::Io(err)
-    }
+/// A guard that times the scope it lives in, returned from `Timer::time`
+///
+/// The time elapsed since the guard was created is added to the timer
+/// when the guard is dropped, or when it's explicitly stopped with `stop`.
+pub struct TimerGuard<'a> {
+    timer: &'a Timer,
+    start_time: Option<Instant>
 }
 
 impl Timer {
     /// Creates a new timer metric with given time scale
     pub fn new(name: &str, time_scale: Time,
-        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, String> {
+        shorthelp_text: &str, longhelp_text: &str) -> Result<Self, Error> {
 
         let metric = Metric::new(
             name,
@@ -49,69 +46,130 @@ impl Timer {
         })
     }
 
-    /// Starts the timer. Returns an error if the timer is
-    /// already started.
+    /// Starts timing a scope, and returns a guard that adds the time
+    /// elapsed to the timer when it's dropped
+    ///
+    /// Unlike `start`, any number of timings can be in progress at once.
+    pub fn time(&self) -> TimerGuard {
+        TimerGuard {
+            timer: self,
+            start_time: Some(Instant::now())
+        }
+    }
+
+    /// Starts the timer. Returns a `TimerAlreadyStarted` error
+    /// if the timer is already started.
     pub fn start(&mut self) -> Result<(), Error> {
         if self.start_time.is_some() {
             return Err(Error::TimerAlreadyStarted)
         }
-        self.start_time = Some(time::now());
+        self.start_time = Some(Instant::now());
         Ok(())
     }
 
     /// Stops the timer, updates the internal metric, and
     /// returns the time elapsed since the last `start`. If
-    /// the timer was stopped too early or too late such that
-    /// the internal nanosecond, microsecond or millisecond value
-    /// over/under-flows, then elapsed time isn't updated.
+    /// the timer was stopped too late such that the internal
+    /// nanosecond, microsecond or millisecond value overflows,
+    /// then elapsed time isn't updated.
     /// 
-    /// Returns `0` if the timer wasn't started before.
+    /// Returns a `TimerNotStarted` error if the timer wasn't
+    /// started before.
     pub fn stop(&mut self) -> Result<i64, Error> {
-        match self.start_time {
-            Some(start_time) => {
-                let duration = time::now() - start_time;
-
-                let elapsed = match self.time_scale {
-                    Time::NSec => duration.num_nanoseconds().unwrap_or(0),
-                    Time::USec => duration.num_microseconds().unwrap_or(0),
-                    Time::MSec => duration.num_microseconds().unwrap_or(0),
-                    Time::Sec => duration.num_seconds(),
-                    Time::Min => duration.num_minutes(),
-                    Time::Hour => duration.num_hours()
-                };
-
-                let val = self.metric.val();
-                self.metric.set_val(val + elapsed)?;
-
-                self.start_time = None;
-
-                Ok(elapsed)
-            },
+        match self.start_time.take() {
+            Some(start_time) => Ok(self.record(start_time)?),
             None => Err(Error::TimerNotStarted)
         }
     }
 
+    fn record(&self, start_time: Instant) -> io::Result<i64> {
+        let elapsed = scaled_duration(start_time.elapsed(), self.time_scale);
+        self.metric.update(|val| val.saturating_add(elapsed))?;
+        Ok(elapsed)
+    }
+
     /// Returns the cumulative time elapsed between every
-    /// `start` and `stop` pair.
-    pub fn elapsed(&mut self) -> i64 {
+    /// `start` and `stop` pair, and over every timed scope.
+    /// It saturates at `i64::MAX` rather than overflowing.
+    pub fn elapsed(&self) -> i64 {
         self.metric.val()
     }
+
+    /// Sets the item ID of the timer, as with `Metric::set_item`
+    pub fn set_item(&mut self, item: u32) -> Result<(), Error> {
+        self.metric.set_item(item)
+    }
+}
+
+impl<'a> TimerGuard<'a> {
+    /// Stops timing, adds the time elapsed to the timer, and
+    /// returns the time elapsed
+    pub fn stop(mut self) -> Result<i64, Error> {
+        match self.start_time.take() {
+            Some(start_time) => Ok(self.timer.record(start_time)?),
+            None => Ok(0)
+        }
+    }
+}
+
+impl<'a> Drop for TimerGuard<'a> {
+    fn drop(&mut self) {
+        if let Some(start_time) = self.start_time.take() {
+            self.timer.record(start_time).ok();
+        }
+    }
+}
+
+// converts a duration to the given time scale, or to
+// 0 if the value overflows
+fn scaled_duration(duration: Duration, time_scale: Time) -> i64 {
+    let secs = duration.as_secs();
+    let nanos = duration.subsec_nanos() as u64;
+
+    let scaled = match time_scale {
+        Time::NSec => secs.checked_mul(1_000_000_000)
+            .and_then(|ns| ns.checked_add(nanos)),
+        Time::USec => secs.checked_mul(1_000_000)
+            .and_then(|us| us.checked_add(nanos / 1_000)),
+        Time::MSec => secs.checked_mul(1_000)
+            .and_then(|ms| ms.checked_add(nanos / 1_000_000)),
+        Time::Sec => Some(secs),
+        Time::Min => Some(secs / 60),
+        Time::Hour => Some(secs / 3600)
+    };
+
+    match scaled {
+        Some(val) if val <= i64::max_value() as u64 => val as i64,
+        _ => 0
+    }
 }
 
 impl MMVWriter for Timer {
     private_impl!{}
 
-    fn write(&mut self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
+    fn write(&self, ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver: Version) -> io::Result<()> {
         self.metric.write(ws, c, mmv_ver)
     }
 
-    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) {
+    fn register(&self, ws: &mut MMVWriterState, mmv_ver: Version) -> Result<(), Error> {
         self.metric.register(ws, mmv_ver)
     }
 
     fn has_mmv2_string(&self) -> bool {
         self.metric.has_mmv2_string()
     }
+
+    fn share(&self) -> Box<MMVWriter + Send + Sync> {
+        self.metric.share()
+    }
+
+    fn id(&self) -> usize {
+        self.metric.id()
+    }
+
+    fn name(&self) -> &str {
+        self.metric.name()
+    }
 }
 
 #[test]
@@ -141,3 +199,58 @@ pub fn test() {
     let elapsed2 = timer.stop().unwrap();
     assert_eq!(timer.elapsed(), elapsed1 + elapsed2);
 }
+
+#[test]
+pub fn test_time_guards() {
+    use super::super::Client;
+    use std::sync::Arc;
+    use std::thread;
+
+    let mut timer = Timer::new("timer_guards", Time::MSec, "", "").unwrap();
+
+    let client = Client::new("timer_guards_test").unwrap();
+    client.export(&mut [&mut timer]).unwrap();
+
+    let timer = Arc::new(timer);
+    let threads: Vec<_> = (0..4).map(|_| {
+        let timer = timer.clone();
+        thread::spawn(move || {
+            let _guard = timer.time();
+            thread::sleep(Duration::from_millis(100));
+        })
+    }).collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert!(timer.elapsed() >= 400);
+    assert!(timer.elapsed() < 4000);
+
+    let before = timer.elapsed();
+    let guard = timer.time();
+    thread::sleep(Duration::from_millis(10));
+    let elapsed = guard.stop().unwrap();
+    assert!(elapsed >= 10);
+    assert_eq!(timer.elapsed(), before + elapsed);
+
+    // the cumulative time saturates, even when a guard is dropped
+    timer.metric.set_val(i64::max_value() - 1).unwrap();
+    drop(TimerGuard {
+        timer: &timer,
+        start_time: Some(Instant::now() - Duration::from_secs(1))
+    });
+    assert_eq!(timer.elapsed(), i64::max_value());
+}
+
+#[test]
+pub fn test_scaled_duration() {
+    let duration = Duration::new(7230, 5_006_007);
+    assert_eq!(scaled_duration(duration, Time::NSec), 7_230_005_006_007);
+    assert_eq!(scaled_duration(duration, Time::USec), 7_230_005_006);
+    assert_eq!(scaled_duration(duration, Time::MSec), 7_230_005);
+    assert_eq!(scaled_duration(duration, Time::Sec), 7230);
+    assert_eq!(scaled_duration(duration, Time::Min), 120);
+    assert_eq!(scaled_duration(duration, Time::Hour), 2);
+
+    let duration = Duration::new(u64::max_value(), 0);
+    assert_eq!(scaled_duration(duration, Time::NSec), 0);
+}
@@ -1,6 +1,7 @@
 use byteorder::WriteBytesExt;
-use memmap::{Mmap, Protection};
+use memmap::{Mmap, MmapViewSync, Protection};
 use regex::bytes::Regex;
+use std::cmp;
 use std::env;
 use std::ffi::{OsStr, OsString};
 use std::fmt;
@@ -9,13 +10,19 @@ use std::fs::{File, OpenOptions};
 use std::io;
 use std::io::{BufReader, Cursor};
 use std::io::prelude::*;
+use std::mem;
 use std::path::{MAIN_SEPARATOR, Path, PathBuf};
 use std::str;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
 use time;
 
 use super::mmv::Version;
 use super::{
     Endian,
+    Error,
     CLUSTER_ID_BIT_LEN,
     HDR_LEN,
     TOC_BLOCK_LEN,
@@ -29,7 +36,13 @@ use super::{
 };
 
 pub mod metric;
-use self::metric::{MMVWriter, MMVWriterState};
+use self::metric::{MMVWriter, MMVWriterState, assign_items, sample_writers};
+
+mod registry;
+pub use self::registry::Registry;
+
+mod batch;
+pub use self::batch::Batch;
 
 static PCP_TMP_DIR_KEY: &'static str = "PCP_TMP_DIR";
 static MMV_DIR_SUFFIX: &'static str = "mmv";
@@ -115,7 +128,7 @@ fn parse_pcp_conf<P: AsRef<Path>>(conf_path: P) -> io::Result<()> {
     Ok(())
 }
 
-fn get_mmv_dir() -> io::Result<PathBuf> {
+pub (crate) fn get_mmv_dir() -> io::Result<PathBuf> {
     let pcp_root = get_pcp_root();
     let mut mmv_dir = pcp_root.clone();
 
@@ -152,7 +165,7 @@ bitflags! {
         const NOPREFIX = 1;
         /// PID check is needed
         const PROCESS  = 2;
-        /// Allow "no value available" values
+        /// Allow "no value available" values, e.g., of cleared metrics
         const SENTINEL = 4;
     }
 }
@@ -191,15 +204,19 @@ impl fmt::Display for MMVFlags {
 }
 
 /// Client used to export metrics
+///
+/// The exported MMV file is removed once the client is dropped, so
+/// the client has to be kept alive for as long as the metrics are
+/// to be exported.
 pub struct Client {
-    flags: MMVFlags,
     cluster_id: u32,
-    mmv_path: PathBuf
+    mmv_path: PathBuf,
+    export: Arc<Mutex<Export>>
 }
 
 impl Client {
     /// Creates a new client with `PROCESS` flag and `0` cluster ID
-    pub fn new(name: &str) -> io::Result<Client> {
+    pub fn new(name: &str) -> Result<Client, Error> {
         Client::new_custom(name, PROCESS, 0)
     }
 
@@ -208,35 +225,280 @@ impl Client {
     /// Note that only the 12 least significant bits of `cluster_id` will be
     /// used.
     pub fn new_custom(name: &str, flags: MMVFlags, cluster_id: u32)
-    -> io::Result<Client> {
+    -> Result<Client, Error> {
         let mmv_path = get_mmv_dir()?.join(name);
         let cluster_id = cluster_id & ((1 << CLUSTER_ID_BIT_LEN) - 1);
 
-        Ok(Client {
+        let export = Export {
             flags: flags,
             cluster_id: cluster_id,
-            mmv_path: mmv_path
+            mmv_path: mmv_path.clone(),
+            gen: 0,
+            writers: Vec::new(),
+            published: false,
+            header: None,
+            batches: 0
+        };
+
+        Ok(Client {
+            cluster_id: cluster_id,
+            mmv_path: mmv_path,
+            export: Arc::new(Mutex::new(export))
         })
     }
     
     /// Exports metrics to an MMV file at `mmv_path`
     ///
-    /// If an MMV file is already present at `mmv_path`, it's overwritten
-    /// with the newer metrics.
-    pub fn export(&self, metrics: &mut [&mut MMVWriter]) -> io::Result<()> {
+    /// The MMV file is written under a temporary name and then renamed to
+    /// `mmv_path`, so readers only ever see a complete MMV. If an MMV file
+    /// is already present at `mmv_path`, it's replaced with the newer metrics.
+    ///
+    /// The client keeps track of the exported metrics, so that the MMV
+    /// can be regenerated when their instances change.
+    ///
+    /// The result is a `DuplicateName` or `DuplicateItem` error if two
+    /// metrics have the same name or item ID set, in which case the
+    /// previously exported MMV is left as it is.
+    pub fn export(&self, metrics: &mut [&mut MMVWriter]) -> Result<(), Error> {
+        let writers = metrics.iter().map(|m| m.share()).collect();
+        lock_export(&self.export).export(writers, self.link())
+    }
+
+    /// Exports every metric registered with the registry to an MMV file
+    /// at `mmv_path`, as with `export`
+    ///
+    /// Metrics that are registered with the registry later on are also
+    /// registered with the client, for as long as it's alive.
+    pub fn export_registry(&self, registry: &Registry) -> Result<(), Error> {
+        registry.export(self.link())
+    }
+
+    /// Registers a metric with an already exported client
+    ///
+    /// The MMV is regenerated with a new generation number, carrying over
+    /// the current values of the metrics that were already exported.
+    /// Registering a metric that's already exported does nothing.
+    ///
+    /// The result is a `DuplicateName` or `DuplicateItem` error if the
+    /// metric has the same name or item ID set as an exported metric.
+    pub fn register(&self, metric: &MMVWriter) -> Result<(), Error> {
+        lock_export(&self.export).register(metric, self.link())
+    }
+
+    /// Unregisters a metric from the client
+    ///
+    /// The MMV is regenerated with a new generation number, without
+    /// the metric.
+    ///
+    /// The result is a `NotExported` error if the metric wasn't exported
+    /// by the client.
+    pub fn unregister(&self, metric: &MMVWriter) -> Result<(), Error> {
+        lock_export(&self.export).unregister(metric, self.link())
+    }
+
+    /// Samples every exported metric whose values are pulled rather
+    /// than set, e.g., a `CallbackMetric`, writing the sampled values
+    /// to the MMV
+    ///
+    /// A metric that fails to be sampled doesn't stop the others from
+    /// being sampled; the first error is returned after sampling them all.
+    pub fn sample(&self) -> Result<(), Error> {
+        self.link().sample()
+    }
+
+    /// Spawns a thread that samples the exported metrics at the given
+    /// interval, as with `sample`, for as long as the client is alive
+    pub fn sample_every(&self, interval: Duration) -> thread::JoinHandle<()> {
+        let link = self.link();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(interval);
+                if link.is_dropped() {
+                    break;
+                }
+                link.sample().ok();
+            }
+        })
+    }
+
+    /// Returns an empty batch of updates, that're applied together
+    /// when the batch is committed
+    pub fn batch(&self) -> Batch {
+        Batch::new(self)
+    }
+
+    /// Returns the cluster ID of the MMV file
+    pub fn cluster_id(&self) -> u32 {
+        self.cluster_id
+    }
+
+    /// Returns the absolute filesystem path of the MMV file
+    pub fn mmv_path(&self) -> &Path {
+        self.mmv_path.as_path()
+    }
+
+    fn link(&self) -> ExportLink {
+        ExportLink(Arc::downgrade(&self.export))
+    }
+}
+
+// metrics exported by a client, and what's needed to write them to an MMV
+struct Export {
+    flags: MMVFlags,
+    cluster_id: u32,
+    mmv_path: PathBuf,
+    // generation of the last MMV written
+    gen: i64,
+    writers: Vec<Box<MMVWriter + Send + Sync>>,
+    // whether an MMV has been written to `mmv_path`
+    published: bool,
+    // header of the MMV at `mmv_path`
+    header: Option<MmapViewSync>,
+    // number of batches being committed, during which the header
+    // is kept locked
+    batches: usize
+}
+
+// offsets of the generation numbers in the MMV header
+const GEN1_OFF: usize = 8;
+const GEN2_OFF: usize = 16;
+
+fn store_gen(header: &MmapViewSync, off: usize, gen: i64) {
+    // the header is page-aligned, so the generations are 8-byte aligned
+    let gen_bits = unsafe { &*(header.ptr().offset(off as isize) as *const AtomicU64) };
+    gen_bits.store((gen as u64).to_le(), Ordering::SeqCst);
+}
+
+fn lock_export(export: &Mutex<Export>) -> MutexGuard<Export> {
+    export.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Link from an exported metric back to the client that exported it,
+/// used to regenerate the MMV when the metric changes
+#[derive(Clone)]
+pub (crate) struct ExportLink(Weak<Mutex<Export>>);
+
+impl ExportLink {
+    /// Regenerates the MMV with the current state of every metric
+    /// in it. Does nothing if the client was dropped.
+    pub (crate) fn regenerate(&self) -> Result<(), Error> {
+        match self.0.upgrade() {
+            Some(export) => lock_export(&export).write(self.clone()),
+            None => Ok(())
+        }
+    }
+
+    /// Exports the given metrics in place of the ones exported so far.
+    /// Does nothing if the client was dropped.
+    pub (crate) fn export(&self, writers: Vec<Box<MMVWriter + Send + Sync>>) -> Result<(), Error> {
+        match self.0.upgrade() {
+            Some(export) => lock_export(&export).export(writers, self.clone()),
+            None => Ok(())
+        }
+    }
+
+    /// Adds a metric to the exported ones, regenerating the MMV.
+    /// Does nothing if the client was dropped.
+    pub (crate) fn register(&self, metric: &MMVWriter) -> Result<(), Error> {
+        match self.0.upgrade() {
+            Some(export) => lock_export(&export).register(metric, self.clone()),
+            None => Ok(())
+        }
+    }
+
+    /// Removes a metric from the exported ones, regenerating the MMV.
+    /// Does nothing if the client was dropped.
+    pub (crate) fn unregister(&self, metric: &MMVWriter) -> Result<(), Error> {
+        match self.0.upgrade() {
+            Some(export) => lock_export(&export).unregister(metric, self.clone()),
+            None => Ok(())
+        }
+    }
+
+    /// Samples the exported metrics. The metrics are sampled without
+    /// holding on to the client, since sampling calls into user code.
+    /// Every metric is sampled even if some fail, and the first error
+    /// is returned. Does nothing if the client was dropped.
+    pub (crate) fn sample(&self) -> Result<(), Error> {
+        let writers: Vec<_> = match self.0.upgrade() {
+            Some(export) => lock_export(&export).writers.iter()
+                .map(|m| m.share())
+                .collect(),
+            None => return Ok(())
+        };
+        sample_writers(&writers)
+    }
+
+    /// Checks if the client was dropped
+    pub (crate) fn is_dropped(&self) -> bool {
+        self.0.upgrade().is_none()
+    }
+
+    /// Checks if both links are to the same client
+    pub (crate) fn links_to_same(&self, other: &ExportLink) -> bool {
+        match (self.0.upgrade(), other.0.upgrade()) {
+            (Some(export), Some(other_export)) => Arc::ptr_eq(&export, &other_export),
+            _ => false
+        }
+    }
+}
+
+impl Export {
+    // replaces the exported metrics, leaving them as they were if
+    // the new ones can't be written
+    fn export(&mut self, writers: Vec<Box<MMVWriter + Send + Sync>>, link: ExportLink)
+    -> Result<(), Error> {
+        let prev_writers = mem::replace(&mut self.writers, writers);
+        let res = self.write(link);
+        if res.is_err() {
+            self.writers = prev_writers;
+        }
+        res
+    }
+
+    fn register(&mut self, metric: &MMVWriter, link: ExportLink) -> Result<(), Error> {
+        if self.writers.iter().any(|m| m.id() == metric.id()) {
+            return Ok(());
+        }
+        self.writers.push(metric.share());
+        let res = self.write(link);
+        if res.is_err() {
+            self.writers.pop();
+        }
+        res
+    }
+
+    // removes the metric, keeping it exported if the MMV can't be
+    // written without it
+    fn unregister(&mut self, metric: &MMVWriter, link: ExportLink) -> Result<(), Error> {
+        let idx = match self.writers.iter().position(|m| m.id() == metric.id()) {
+            Some(idx) => idx,
+            None => return Err(Error::NotExported)
+        };
+        let writer = self.writers.remove(idx);
+        let res = self.write(link);
+        if res.is_err() {
+            self.writers.insert(idx, writer);
+        }
+        res
+    }
+
+    fn write(&mut self, link: ExportLink) -> Result<(), Error> {
         let mut ws = MMVWriterState::new();
+        ws.export = Some(link);
 
         let mut mmv_ver = Version::V1;
-        for m in metrics.iter() {
+        for m in self.writers.iter() {
             if m.has_mmv2_string() {
                 mmv_ver = Version::V2;
                 break;
             }
         }
 
-        for m in metrics.iter() {
-            m.register(&mut ws, mmv_ver);
+        for m in self.writers.iter() {
+            m.register(&mut ws, mmv_ver)?;
         }
+        assign_items(&mut ws)?;
 
         if ws.n_metrics > 0 {
             ws.n_toc += 2 /* Metric and Value TOC */;
@@ -247,7 +509,13 @@ impl Client {
         }
 
         if ws.n_indoms > 0 {
-            ws.n_toc += 2 /* Indom and Instance TOC */;
+            ws.n_toc += 1 /* Indom TOC */;
+        }
+
+        // indoms can be empty, e.g., for a family without any label
+        // values yet, in which case there's no Instance TOC
+        if ws.n_instances > 0 {
+            ws.n_toc += 1 /* Instance TOC */;
         }
 
         /*
@@ -267,11 +535,11 @@ impl Client {
             -- Values section
             -- Strings section
             
-            After writing, every metric is given ownership
-            of the respective memory-mapped slice that contains
-            the metric's value. This is to ensure that the metric
-            is *only* able to write to it's value's slice when updating
-            it's value.
+            After writing, every metric's value slot is re-pointed
+            at the respective memory-mapped slice that contains
+            the metric's value, carrying over it's current value.
+            This is to ensure that the metric is *only* able to
+            write to it's value's slice when updating it's value.
         */
 
         let hdr_toc_len = HDR_LEN + TOC_BLOCK_LEN*ws.n_toc;
@@ -301,12 +569,23 @@ impl Client {
             + STRING_BLOCK_LEN*ws.n_strings
         ) as usize;
 
+        // the MMV is written under a temporary name and renamed into place
+        // once complete, so that readers never see a partially written MMV;
+        // the renaming also leaves the previous MMV intact for metrics that
+        // are still writing to it until they're re-pointed at the new one
+        let tmp_path = self.tmp_path();
+        if let Err(err) = fs::remove_file(&tmp_path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                return Err(Error::from(err));
+            }
+        }
+
         let mut file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
             .truncate(true)
-            .open(&self.mmv_path)?;
+            .open(&tmp_path)?;
 
         file.write(&vec![0; mmv_size])?;
 
@@ -317,35 +596,100 @@ impl Client {
         let mut mmap_view = unsafe { ws.mmap_view.as_mut().unwrap().clone() };
         let mut c = Cursor::new(unsafe { mmap_view.as_mut_slice() });
 
+        self.gen = self.next_gen();
+
+        ws.gen = self.gen;
         ws.flags = self.flags.bits();
         ws.cluster_id = self.cluster_id;
         write_mmv_header(&mut ws, &mut c, mmv_ver)?;
 
-        write_toc_block(1, ws.n_indoms as u32, ws.indom_sec_off, &mut c)?;
-        write_toc_block(2, ws.n_instances as u32, ws.instance_sec_off, &mut c)?;
-        write_toc_block(3, ws.n_metrics as u32, ws.metric_sec_off, &mut c)?;
-        write_toc_block(4, ws.n_values as u32, ws.value_sec_off, &mut c)?;
-        write_toc_block(5, ws.n_strings as u32, ws.string_sec_off, &mut c)?;
+        // TOC blocks are written for the same sections counted above; the
+        // Value TOC is written along with the Metric TOC even if every
+        // metric has an empty indom, since readers expect both
+        if ws.n_indoms > 0 {
+            write_toc_block(1, ws.n_indoms as u32, ws.indom_sec_off, &mut c)?;
+        }
+        if ws.n_instances > 0 {
+            write_toc_block(2, ws.n_instances as u32, ws.instance_sec_off, &mut c)?;
+        }
+        if ws.n_metrics > 0 {
+            write_toc_block(3, ws.n_metrics as u32, ws.metric_sec_off, &mut c)?;
+            write_toc_block(4, ws.n_values as u32, ws.value_sec_off, &mut c)?;
+        }
+        if ws.n_strings > 0 {
+            write_toc_block(5, ws.n_strings as u32, ws.string_sec_off, &mut c)?;
+        }
 
-        for m in metrics.iter_mut() {
+        for m in self.writers.iter() {
             m.write(&mut ws, &mut c, mmv_ver)?;
         }
 
-        // unlock header; has to be done last
-        c.set_position(ws.gen2_off);
-        c.write_i64::<Endian>(ws.gen)?;
-        
+        // unlock header; has to be done last, and only once every batch
+        // being committed is done
+        if self.batches == 0 {
+            c.set_position(ws.gen2_off);
+            c.write_i64::<Endian>(ws.gen)?;
+        }
+
+        let mut header = unsafe { ws.mmap_view.as_ref().unwrap().clone() };
+        header.restrict(0, HDR_LEN as usize)?;
+
+        fs::rename(&tmp_path, &self.mmv_path)?;
+        self.published = true;
+        self.header = Some(header);
+
         Ok(())
     }
 
-    /// Returns the cluster ID of the MMV file
-    pub fn cluster_id(&self) -> u32 {
-        self.cluster_id
+    // consumers reload the MMV when the generation changes, so
+    // it has to be bumped even if changed within a second
+    fn next_gen(&self) -> i64 {
+        cmp::max(time::now().to_timespec().sec, self.gen + 1)
     }
 
-    /// Returns the absolute filesystem path of the MMV file
-    pub fn mmv_path(&self) -> &Path {
-        self.mmv_path.as_path()
+    // locks the header, i.e., makes the generations mismatch, so that
+    // readers checking them don't see the updates of a batch until it's
+    // committed
+    fn begin_batch(&mut self) {
+        if self.batches == 0 {
+            if let Some(ref header) = self.header {
+                store_gen(header, GEN2_OFF, 0);
+            }
+        }
+        self.batches += 1;
+    }
+
+    // unlocks the header with a new generation once every batch being
+    // committed is done; the MMV may have been regenerated in the meantime
+    fn end_batch(&mut self) {
+        self.batches -= 1;
+        if self.batches > 0 {
+            return;
+        }
+        let gen = self.next_gen();
+        if let Some(ref header) = self.header {
+            store_gen(header, GEN1_OFF, gen);
+            store_gen(header, GEN2_OFF, gen);
+        }
+        self.gen = gen;
+    }
+
+    // dot-files in the MMV directory are skipped by the MMV PMDA
+    fn tmp_path(&self) -> PathBuf {
+        let mut name = OsString::from(".");
+        if let Some(file_name) = self.mmv_path.file_name() {
+            name.push(file_name);
+        }
+        name.push(".tmp");
+        self.mmv_path.with_file_name(name)
+    }
+}
+
+impl Drop for Export {
+    fn drop(&mut self) {
+        if self.published {
+            fs::remove_file(&self.mmv_path).ok();
+        }
     }
 }
 
@@ -360,7 +704,6 @@ fn write_mmv_header(ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver:
     }
 
     // generation1
-    ws.gen = time::now().to_timespec().sec;
     c.write_i64::<Endian>(ws.gen)?;
     // generation2
     ws.gen2_off = c.position();
@@ -376,15 +719,12 @@ fn write_mmv_header(ws: &mut MMVWriterState, c: &mut Cursor<&mut [u8]>, mmv_ver:
 }
 
 fn write_toc_block(sec: u32, entries: u32, sec_off: u64, c: &mut Cursor<&mut [u8]>) -> io::Result<()> {
-    if entries > 0 {
-        // section type
-        c.write_u32::<Endian>(sec)?;
-        // no. of entries
-        c.write_u32::<Endian>(entries)?;
-        // section offset
-        c.write_u64::<Endian>(sec_off)?;
-    }
-    Ok(())
+    // section type
+    c.write_u32::<Endian>(sec)?;
+    // no. of entries
+    c.write_u32::<Endian>(entries)?;
+    // section offset
+    c.write_u64::<Endian>(sec_off)
 }
 
 #[test]
@@ -429,6 +769,123 @@ fn test_mmv_header() {
     assert_eq!(client.cluster_id(), cursor.read_u32::<Endian>().unwrap());
 }
 
+#[test]
+fn test_register_unregister() {
+    use super::mmv::dump;
+    use self::metric::{Counter, Gauge};
+
+    let mut counter = Counter::new("registered_counter", 0, "", "").unwrap();
+    let gauge = Gauge::new("registered_gauge", 0.0, "", "").unwrap();
+
+    let client = Client::new("register_test").unwrap();
+    client.export(&mut [&mut counter]).unwrap();
+    counter.inc(5).unwrap();
+    let gen = dump(client.mmv_path()).unwrap().header().gen1();
+
+    client.register(&gauge).unwrap();
+    client.register(&gauge).unwrap();
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert!(mmv.header().gen1() > gen);
+    assert_eq!(mmv.metric_blks().len(), 2);
+
+    // the counter's value is carried over, and it keeps
+    // writing to the regenerated MMV
+    counter.up().unwrap();
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert!(mmv.value_blks().values().any(|v| v.value() == 6));
+
+    // the counter stays exported if the MMV can't be regenerated
+    let tmp_path = client.mmv_path().with_file_name(".register_test.tmp");
+    fs::create_dir(&tmp_path).unwrap();
+    assert!(client.unregister(&counter).is_err());
+    fs::remove_dir(&tmp_path).unwrap();
+    assert_eq!(dump(client.mmv_path()).unwrap().metric_blks().len(), 2);
+
+    client.unregister(&counter).unwrap();
+    match client.unregister(&counter) {
+        Err(Error::NotExported) => {},
+        _ => panic!("counter shouldn't be exported")
+    }
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert_eq!(mmv.metric_blks().len(), 1);
+    assert_eq!(counter.val(), 6);
+}
+
+#[test]
+fn test_publish_and_remove() {
+    use self::metric::Counter;
+
+    let mut counter = Counter::new("published_counter", 0, "", "").unwrap();
+
+    let client = Client::new("publish_test").unwrap();
+    let mmv_path = client.mmv_path().to_path_buf();
+    let tmp_path = mmv_path.with_file_name(".publish_test.tmp");
+
+    client.export(&mut [&mut counter]).unwrap();
+    assert!(mmv_path.exists());
+    assert!(!tmp_path.exists());
+
+    drop(client);
+    assert!(!mmv_path.exists());
+
+    // metrics remain usable after the client is gone
+    counter.up().unwrap();
+    assert_eq!(counter.val(), 1);
+}
+
+#[test]
+fn test_duplicate_items() {
+    use self::metric::{Counter, Gauge, Metric, Semantics, Unit};
+
+    let mut counter = Counter::new("duplicate_item", 0, "", "").unwrap();
+    let mut gauge = Gauge::new("duplicate_item", 0.0, "", "").unwrap();
+
+    let client = Client::new("duplicate_items_test").unwrap();
+    match client.export(&mut [&mut counter, &mut gauge]) {
+        Err(Error::DuplicateName(ref name)) => assert_eq!(name, "duplicate_item"),
+        _ => panic!("metric names should be duplicate")
+    }
+    assert!(!client.mmv_path().exists());
+
+    let mut gauge = Gauge::new("other_item", 0.0, "", "").unwrap();
+    counter.set_item(7).unwrap();
+    gauge.set_item(7).unwrap();
+    match client.export(&mut [&mut counter, &mut gauge]) {
+        Err(Error::DuplicateItem { item, .. }) => assert_eq!(item, 7),
+        _ => panic!("item IDs should be duplicate")
+    }
+
+    gauge.set_item(8).unwrap();
+    client.export(&mut [&mut counter, &mut gauge]).unwrap();
+
+    let mut other = Counter::new("another_item", 0, "", "").unwrap();
+    other.set_item(8).unwrap();
+    assert!(client.register(&other).is_err());
+    assert!(counter.set_item(1 << 10).is_err());
+
+    let mmv = super::mmv::dump(client.mmv_path()).unwrap();
+    let mut items: Vec<u32> = mmv.metric_blks().values().map(|m| m.item().unwrap()).collect();
+    items.sort();
+    assert_eq!(items, vec![7, 8]);
+
+    // a hashed item ID that's taken is replaced with the next free one,
+    // even if the metric taking it is registered later on
+    let mut hashed = Metric::new("hashed_item", 0, Semantics::Counter, Unit::new(), "", "").unwrap();
+    let hashed_item = hashed.item();
+    let mut gauge = Gauge::new("set_item", 0.0, "", "").unwrap();
+    gauge.set_item(hashed_item).unwrap();
+    let mut next = Counter::new("next_item", 0, "", "").unwrap();
+    next.set_item((hashed_item + 1) % (1 << 10)).unwrap();
+    client.export(&mut [&mut hashed, &mut gauge, &mut next]).unwrap();
+
+    let mmv = super::mmv::dump(client.mmv_path()).unwrap();
+    let mut items: Vec<u32> = mmv.metric_blks().values().map(|m| m.item().unwrap()).collect();
+    items.sort();
+    let mut expected = vec![hashed_item, (hashed_item + 1) % (1 << 10), (hashed_item + 2) % (1 << 10)];
+    expected.sort();
+    assert_eq!(items, expected);
+}
+
 #[test]
 fn test_mmv_dir() {
     let pcp_root = get_pcp_root();
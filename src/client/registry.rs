@@ -0,0 +1,159 @@
+use std::sync::{Mutex, MutexGuard};
+
+use super::super::Error;
+use super::ExportLink;
+use super::metric::MMVWriter;
+
+/// A registry of metrics that are exported together
+///
+/// Metrics are registered as they're created, possibly in different
+/// parts of a program, and are exported at once with
+/// `Client::export_registry`. Metrics registered after the registry
+/// is exported are added to the clients that exported it.
+///
+/// A process-global registry is available with `Registry::global`.
+pub struct Registry {
+    inner: Mutex<Inner>
+}
+
+struct Inner {
+    writers: Vec<Box<MMVWriter + Send + Sync>>,
+    // clients that exported the registry
+    exports: Vec<ExportLink>
+}
+
+lazy_static! {
+    static ref GLOBAL_REGISTRY: Registry = Registry::new();
+}
+
+impl Registry {
+    /// Creates a new empty registry
+    pub fn new() -> Self {
+        Registry {
+            inner: Mutex::new(Inner {
+                writers: Vec::new(),
+                exports: Vec::new()
+            })
+        }
+    }
+
+    /// Returns the process-global registry
+    pub fn global() -> &'static Registry {
+        &GLOBAL_REGISTRY
+    }
+
+    fn lock_inner(&self) -> MutexGuard<Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Registers a metric and returns it, so that a metric can be
+    /// registered as it's created
+    ///
+    /// If the registry is already exported, the metric is also registered
+    /// with the exporting clients, as with `Client::register`. If any of
+    /// them fails to register it, it's unregistered from the others.
+    ///
+    /// The result is a `DuplicateName` error if a metric with the same
+    /// name is already registered.
+    pub fn register<M: MMVWriter>(&self, metric: M) -> Result<M, Error> {
+        let mut inner = self.lock_inner();
+        if inner.writers.iter().any(|m| m.name() == metric.name()) {
+            return Err(Error::DuplicateName(metric.name().to_owned()));
+        }
+
+        inner.exports.retain(|export| !export.is_dropped());
+        for (i, export) in inner.exports.iter().enumerate() {
+            if let Err(err) = export.register(&metric) {
+                // the error to report is the registration's, so any
+                // failure to roll it back is ignored
+                for registered in inner.exports[..i].iter() {
+                    let _ = registered.unregister(&metric);
+                }
+                return Err(err);
+            }
+        }
+
+        inner.writers.push(metric.share());
+        Ok(metric)
+    }
+
+    /// Returns the names of the registered metrics, in the
+    /// order they were registered
+    pub fn metric_names(&self) -> Vec<String> {
+        self.lock_inner().writers.iter()
+            .map(|m| m.name().to_owned())
+            .collect()
+    }
+
+    /// Returns the number of registered metrics
+    pub fn len(&self) -> usize {
+        self.lock_inner().writers.len()
+    }
+
+    /// Checks if no metrics are registered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub (super) fn export(&self, link: ExportLink) -> Result<(), Error> {
+        let mut inner = self.lock_inner();
+        let writers = inner.writers.iter().map(|m| m.share()).collect();
+        link.export(writers)?;
+
+        if !inner.exports.iter().any(|export| export.links_to_same(&link)) {
+            inner.exports.push(link);
+        }
+        Ok(())
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+#[test]
+fn test() {
+    use super::super::mmv::dump;
+    use super::Client;
+    use super::metric::{Counter, Gauge};
+
+    let registry = Registry::new();
+    let counter = registry.register(
+        Counter::new("registry_counter", 0, "", "").unwrap()
+    ).unwrap();
+    registry.register(Gauge::new("registry_gauge", 0.0, "", "").unwrap()).unwrap();
+    assert_eq!(registry.metric_names(), vec!["registry_counter", "registry_gauge"]);
+
+    assert!(registry.register(
+        Counter::new("registry_counter", 0, "", "").unwrap()
+    ).is_err());
+    assert_eq!(registry.len(), 2);
+
+    let client = Client::new("registry_test").unwrap();
+    client.export_registry(&registry).unwrap();
+    counter.inc(3).unwrap();
+    assert_eq!(dump(client.mmv_path()).unwrap().metric_blks().len(), 2);
+
+    // metrics registered later on are added to the exported MMV
+    registry.register(Gauge::new("registry_late_gauge", 0.0, "", "").unwrap()).unwrap();
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert_eq!(mmv.metric_blks().len(), 3);
+    assert!(mmv.value_blks().values().any(|v| v.value() == 3));
+
+    // a metric that a client fails to register isn't left
+    // registered with the other clients
+    let other_client = Client::new("registry_other_test").unwrap();
+    other_client.export_registry(&registry).unwrap();
+    other_client.register(&Counter::new("registry_clash", 0, "", "").unwrap()).unwrap();
+    assert!(registry.register(Counter::new("registry_clash", 0, "", "").unwrap()).is_err());
+    assert_eq!(dump(client.mmv_path()).unwrap().metric_blks().len(), 3);
+    assert_eq!(registry.len(), 3);
+
+    let global_counter = Registry::global().register(
+        Counter::new("global_registry_counter", 0, "", "").unwrap()
+    ).unwrap();
+    assert!(Registry::global().metric_names().contains(&"global_registry_counter".to_owned()));
+    global_counter.up().unwrap();
+}
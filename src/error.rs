@@ -0,0 +1,153 @@
+use hdrsample;
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Error encountered while creating, updating or exporting metrics
+#[derive(Debug)]
+pub enum Error {
+    /// Metric name longer than the maximum length
+    NameTooLong(String),
+    /// Metric name that isn't valid in the PCP namespace
+    InvalidName(String),
+    /// Instance name longer than the maximum length
+    InstanceTooLong(String),
+    /// Short or long help text longer than the maximum length
+    HelpTextTooLong(String),
+    /// Unit dimension out of the range `[-8, 7]`
+    InvalidUnitDimension(i8),
+    /// Item ID that doesn't fit in 10 bits
+    InvalidItem(u32),
+    /// Another metric with the same name is exported
+    DuplicateName(String),
+    /// Another metric with the same item ID is exported
+    DuplicateItem {
+        item: u32,
+        name: String,
+        other_name: String
+    },
+    /// Instance isn't part of the metric
+    UnknownInstance(String),
+    /// Instance is already part of the metric
+    DuplicateInstance(String),
+    /// Label name or value that's empty or contains a `,` or `=`
+    InvalidLabel(String),
+    /// Number of label values that differs from the number of labels
+    LabelCountMismatch {
+        expected: usize,
+        found: usize
+    },
+    /// Metric isn't exported by the client
+    NotExported,
+    /// Timer was started before being stopped
+    TimerAlreadyStarted,
+    /// Timer was stopped before being started
+    TimerNotStarted,
+    /// Error while creating the HDR histogram of a histogram metric
+    HistogramCreation(hdrsample::CreationError),
+    /// Error while recording a value in the HDR histogram of a histogram metric
+    HistogramRecord(hdrsample::RecordError),
+    /// Error while merging the HDR histograms of a rolling histogram metric
+    HistogramAddition(hdrsample::AdditionError),
+    /// IO error while writing an MMV
+    Io(io::Error)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NameTooLong(ref name) =>
+                write!(f, "metric name \"{}\" is too long", name),
+            Error::InvalidName(ref name) =>
+                write!(f, "metric name \"{}\" isn't a valid PCP name", name),
+            Error::InstanceTooLong(ref instance) =>
+                write!(f, "instance \"{}\" is too long", instance),
+            Error::HelpTextTooLong(ref text) =>
+                write!(f, "help text \"{}\" is too long", text),
+            Error::InvalidUnitDimension(dim) =>
+                write!(f, "unit dimension {} is out of range [-8, 7]", dim),
+            Error::InvalidItem(item) =>
+                write!(f, "item ID {} doesn't fit in 10 bits", item),
+            Error::DuplicateName(ref name) =>
+                write!(f, "duplicate metric name \"{}\"", name),
+            Error::DuplicateItem { item, ref name, ref other_name } =>
+                write!(f, "metrics \"{}\" and \"{}\" have the same item ID {}",
+                    other_name, name, item),
+            Error::UnknownInstance(ref instance) =>
+                write!(f, "unknown instance \"{}\"", instance),
+            Error::DuplicateInstance(ref instance) =>
+                write!(f, "duplicate instance \"{}\"", instance),
+            Error::InvalidLabel(ref label) =>
+                write!(f, "label \"{}\" is empty or contains ',' or '='", label),
+            Error::LabelCountMismatch { expected, found } =>
+                write!(f, "expected {} label values, found {}", expected, found),
+            Error::NotExported => write!(f, "metric isn't exported"),
+            Error::TimerAlreadyStarted => write!(f, "timer already started"),
+            Error::TimerNotStarted => write!(f, "timer not started"),
+            Error::HistogramCreation(ref err) =>
+                write!(f, "error creating HDR histogram: {:?}", err),
+            Error::HistogramRecord(ref err) =>
+                write!(f, "error recording value in HDR histogram: {:?}", err),
+            Error::HistogramAddition(ref err) =>
+                write!(f, "error merging HDR histograms: {:?}", err),
+            Error::Io(ref err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::NameTooLong(_) => "metric name too long",
+            Error::InvalidName(_) => "invalid metric name",
+            Error::InstanceTooLong(_) => "instance too long",
+            Error::HelpTextTooLong(_) => "help text too long",
+            Error::InvalidUnitDimension(_) => "invalid unit dimension",
+            Error::InvalidItem(_) => "invalid item ID",
+            Error::DuplicateName(_) => "duplicate metric name",
+            Error::DuplicateItem { .. } => "duplicate item ID",
+            Error::UnknownInstance(_) => "unknown instance",
+            Error::DuplicateInstance(_) => "duplicate instance",
+            Error::InvalidLabel(_) => "invalid label",
+            Error::LabelCountMismatch { .. } => "label count mismatch",
+            Error::NotExported => "metric not exported",
+            Error::TimerAlreadyStarted => "timer already started",
+            Error::TimerNotStarted => "timer not started",
+            Error::HistogramCreation(_) => "HDR histogram creation error",
+            Error::HistogramRecord(_) => "HDR histogram record error",
+            Error::HistogramAddition(_) => "HDR histogram addition error",
+            Error::Io(ref err) => err.description()
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<hdrsample::CreationError> for Error {
+    fn from(err: hdrsample::CreationError) -> Error {
+        Error::HistogramCreation(err)
+    }
+}
+
+impl From<hdrsample::RecordError> for Error {
+    fn from(err: hdrsample::RecordError) -> Error {
+        Error::HistogramRecord(err)
+    }
+}
+
+impl From<hdrsample::AdditionError> for Error {
+    fn from(err: hdrsample::AdditionError) -> Error {
+        Error::HistogramAddition(err)
+    }
+}
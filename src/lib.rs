@@ -32,5 +32,8 @@ type Endian = byteorder::LittleEndian;
 #[macro_use]
 mod private;
 
+mod error;
+pub use error::Error;
+
 pub mod client;
 pub mod mmv;
@@ -30,8 +30,9 @@ fn write_indoms(f: &mut fmt::Formatter, indom_toc: &TocBlk, mmv: &MMV) -> fmt::R
             write!(f, "      ")?;
             match *indom.short_help_offset() {
                 Some(ref short_help_offset) => {
-                    let shortext = mmv.string_blks().get(short_help_offset).unwrap().string();
-                    writeln!(f, "shorttext={}", shortext)?;
+                    write!(f, "shorttext=")?;
+                    write_string_blk(f, short_help_offset, mmv)?;
+                    writeln!(f, "")?;
                 }
                 None => writeln!(f, "(no shorttext)")?
             }
@@ -39,8 +40,9 @@ fn write_indoms(f: &mut fmt::Formatter, indom_toc: &TocBlk, mmv: &MMV) -> fmt::R
             write!(f, "      ")?;
             match *indom.long_help_offset() {
                 Some(ref long_help_offset) => {
-                    let longtext = mmv.string_blks().get(long_help_offset).unwrap().string();
-                    writeln!(f, "longtext={}", longtext)?
+                    write!(f, "longtext=")?;
+                    write_string_blk(f, long_help_offset, mmv)?;
+                    writeln!(f, "")?;
                 }
                 None => writeln!(f, "(no longtext)")?
             }
@@ -50,14 +52,19 @@ fn write_indoms(f: &mut fmt::Formatter, indom_toc: &TocBlk, mmv: &MMV) -> fmt::R
     Ok(())
 }
 
+// note: doesn't write newline at the end
+fn write_string_blk(f: &mut fmt::Formatter, offset: &u64, mmv: &MMV) -> fmt::Result {
+    match mmv.string_blks().get(offset) {
+        Some(string) => write!(f, "{}", string.string()),
+        None => write!(f, "(invalid string offset {})", offset)
+    }
+}
+
 // note: doesn't write newline at the end
 fn write_version_specific_string(f: &mut fmt::Formatter, string: &VersionSpecificString, mmv: &MMV) -> fmt::Result {
     match string {
         &VersionSpecificString::String(ref string) => write!(f, "{}", string),
-        &VersionSpecificString::Offset(ref offset) => {
-            let string = mmv.string_blks().get(offset).unwrap().string();
-            write!(f, "{}", string)
-        }
+        &VersionSpecificString::Offset(ref offset) => write_string_blk(f, offset, mmv)
     }
 }
 
@@ -69,8 +76,7 @@ fn write_instances(f: &mut fmt::Formatter, instance_toc: &TocBlk, mmv: &MMV) ->
         write!(f, "  ")?;
         match *instance.indom_offset() {
             Some(ref indom_offset) => {
-                let indom = mmv.indom_blks().get(indom_offset).unwrap();
-                match *indom.indom() {
+                match mmv.indom_blks().get(indom_offset).and_then(|indom| *indom.indom()) {
                     Some(ref indom_id) => write!(f, "[{}", indom_id)?,
                     None => write!(f, "[(no indom)")?
                 }
@@ -119,8 +125,9 @@ fn write_metrics(f: &mut fmt::Formatter, metric_toc: &TocBlk, mmv: &MMV) -> fmt:
             write!(f, "      ")?;
             match *metric.short_help_offset() {
                 Some(ref short_help_offset) => {
-                    let shortext = mmv.string_blks().get(short_help_offset).unwrap().string();
-                    writeln!(f, "shorttext={}", shortext)?;
+                    write!(f, "shorttext=")?;
+                    write_string_blk(f, short_help_offset, mmv)?;
+                    writeln!(f, "")?;
                 }
                 None => writeln!(f, "(no shorttext)")?
             }
@@ -128,8 +135,9 @@ fn write_metrics(f: &mut fmt::Formatter, metric_toc: &TocBlk, mmv: &MMV) -> fmt:
             write!(f, "      ")?;
             match *metric.long_help_offset() {
                 Some(ref long_help_offset) => {
-                    let longtext = mmv.string_blks().get(long_help_offset).unwrap().string();
-                    writeln!(f, "longtext={}", longtext)?;
+                    write!(f, "longtext=")?;
+                    write_string_blk(f, long_help_offset, mmv)?;
+                    writeln!(f, "")?;
                 }
                 None => writeln!(f, "(no longtext)")?
             }
@@ -144,14 +152,15 @@ fn write_values(f: &mut fmt::Formatter, value_toc: &TocBlk, mmv: &MMV) -> fmt::R
         value_toc._toc_index(), value_toc._mmv_offset(), value_toc.sec_offset(), value_toc.entries())?;
 
     for (offset, value) in mmv.value_blks() {
-        if let Some(ref metric_offset) = *value.metric_offset() {
-            let metric = mmv.metric_blks().get(&metric_offset).unwrap();
+        let metric = value.metric_offset().and_then(|metric_offset| mmv.metric_blks().get(&metric_offset));
+        if let Some(metric) = metric {
             if let Some(item) = *metric.item() {
                 write!(f, "  [{}/{}] ", item, offset)?;
                 write_version_specific_string(f, metric.name(), mmv)?;
 
-                if let Some(ref instance_offset) = *value.instance_offset() {
-                    let instance = mmv.instance_blks().get(&instance_offset).unwrap();
+                let instance = value.instance_offset()
+                    .and_then(|instance_offset| mmv.instance_blks().get(&instance_offset));
+                if let Some(instance) = instance {
                     write!(f, "[{} or \"", instance.internal_id())?;
                     write_version_specific_string(f, instance.external_id(), mmv)?;
                     write!(f, "\"]")?;
@@ -160,8 +169,9 @@ fn write_values(f: &mut fmt::Formatter, value_toc: &TocBlk, mmv: &MMV) -> fmt::R
                 write!(f, " = ")?;
                 match *value.string_offset() {
                     Some(ref string_offset) => {
-                        let string = mmv.string_blks().get(string_offset).unwrap();
-                        writeln!(f, "\"{}\"", string.string())?;
+                        write!(f, "\"")?;
+                        write_string_blk(f, string_offset, mmv)?;
+                        writeln!(f, "\"")?;
                     }
                     None => {
                         match MTCode::from_u32(metric.typ()) {
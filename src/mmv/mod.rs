@@ -1,6 +1,5 @@
 use byteorder::ReadBytesExt;
 use std::collections::BTreeMap;
-use std::ffi::CStr; // Used to read null-terminated strings in MMV files
 use std::fmt;
 use std::fs::File;
 use std::io;
@@ -11,6 +10,15 @@ use std::str;
 
 mod mmvfmt;
 
+mod view;
+pub use self::view::{MetricView, Value};
+
+mod scan;
+pub use self::scan::{MMVSummary, scan, scan_dir};
+
+mod stale;
+pub use self::stale::{Cleanup, Producer, clean, clean_mmv_dir};
+
 const INDOM_TOC_CODE: u32 = 1;
 const INSTANCE_TOC_CODE: u32 = 2;
 const METRIC_TOC_CODE: u32 = 3;
@@ -72,7 +80,15 @@ impl fmt::Display for MTCode {
 use super::{
     Endian,
     MMV1_NAME_MAX_LEN,
+    HDR_LEN,
+    TOC_BLOCK_LEN,
+    INDOM_BLOCK_LEN,
+    VALUE_BLOCK_LEN,
     STRING_BLOCK_LEN,
+    INSTANCE_BLOCK_LEN_MMV1,
+    METRIC_BLOCK_LEN_MMV1,
+    INSTANCE_BLOCK_LEN_MMV2,
+    METRIC_BLOCK_LEN_MMV2,
     CLUSTER_ID_BIT_LEN,
     ITEM_BIT_LEN,
     INDOM_BIT_LEN
@@ -94,6 +110,14 @@ fn is_valid_blk_offset(offset: u64) -> bool {
     offset != 0
 }
 
+// reads a null-terminated string from a fixed-length buffer
+fn string_from_cstr(bytes: &[u8]) -> Result<String, MMVDumpError> {
+    match bytes.iter().position(|&b| b == 0) {
+        Some(len) => Ok(str::from_utf8(&bytes[..len])?.to_owned()),
+        None => Err(MMVDumpError::UnterminatedString)
+    }
+}
+
 /// Error encountered while reading and parsing an MMV
 #[derive(Debug)]
 pub enum MMVDumpError {
@@ -102,7 +126,45 @@ pub enum MMVDumpError {
     /// IO error while reading MMV
     Io(io::Error),
     /// UTF-8 error while parsing MMV strings
-    Utf8(str::Utf8Error)
+    Utf8(str::Utf8Error),
+    /// String without a null terminator
+    UnterminatedString,
+    /// Section of the TOC block at `toc_offset` lies outside the MMV
+    SectionOutOfBounds { toc_offset: u64, sec_offset: u64, entries: u32 },
+    /// Block at `blk_offset` refers to a string block that doesn't exist
+    InvalidStringOffset { blk_offset: u64, offset: u64 },
+    /// Block at `blk_offset` refers to a metric block that doesn't exist
+    InvalidMetricOffset { blk_offset: u64, offset: u64 },
+    /// Block at `blk_offset` refers to an instance block that doesn't exist
+    InvalidInstanceOffset { blk_offset: u64, offset: u64 },
+    /// Block at `blk_offset` refers to an indom block that doesn't exist
+    InvalidIndomOffset { blk_offset: u64, offset: u64 },
+    /// Metric block at `blk_offset` has an indom that no indom block has
+    UnknownIndom { blk_offset: u64, indom: u32 }
+}
+
+impl fmt::Display for MMVDumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MMVDumpError::InvalidMMV(ref err) => write!(f, "{}", err),
+            MMVDumpError::Io(ref err) => write!(f, "{}", err),
+            MMVDumpError::Utf8(ref err) => write!(f, "{}", err),
+            MMVDumpError::UnterminatedString => write!(f, "String isn't null-terminated"),
+            MMVDumpError::SectionOutOfBounds { toc_offset, sec_offset, entries } =>
+                write!(f, "Section at offset {} with {} entries, of TOC at offset {}, is out of bounds",
+                    sec_offset, entries, toc_offset),
+            MMVDumpError::InvalidStringOffset { blk_offset, offset } =>
+                write!(f, "Invalid string offset {} in block at offset {}", offset, blk_offset),
+            MMVDumpError::InvalidMetricOffset { blk_offset, offset } =>
+                write!(f, "Invalid metric offset {} in block at offset {}", offset, blk_offset),
+            MMVDumpError::InvalidInstanceOffset { blk_offset, offset } =>
+                write!(f, "Invalid instance offset {} in block at offset {}", offset, blk_offset),
+            MMVDumpError::InvalidIndomOffset { blk_offset, offset } =>
+                write!(f, "Invalid indom offset {} in block at offset {}", offset, blk_offset),
+            MMVDumpError::UnknownIndom { blk_offset, indom } =>
+                write!(f, "Unknown indom {} in metric block at offset {}", indom, blk_offset)
+        }
+    }
 }
 
 impl From<io::Error> for MMVDumpError {
@@ -339,10 +401,7 @@ impl MetricBlk {
             Version::V1 => {
                 let mut name_bytes = [0; MMV1_NAME_MAX_LEN as usize];
                 r.read_exact(&mut name_bytes)?;
-                let cstr = unsafe {
-                    CStr::from_ptr(name_bytes.as_ptr() as *const i8)
-                };
-                VersionSpecificString::String(cstr.to_str()?.to_owned())
+                VersionSpecificString::String(string_from_cstr(&name_bytes)?)
             },
             Version::V2 => {
                 VersionSpecificString::Offset(r.read_u64::<Endian>()?)
@@ -515,10 +574,7 @@ impl InstanceBlk {
             Version::V1 => {
                 let mut external_id_bytes = [0; MMV1_NAME_MAX_LEN as usize];
                 r.read_exact(&mut external_id_bytes)?;
-                let cstr = unsafe {
-                    CStr::from_ptr(external_id_bytes.as_ptr() as *const i8)
-                };
-                VersionSpecificString::String(cstr.to_str()?.to_owned())
+                VersionSpecificString::String(string_from_cstr(&external_id_bytes)?)
             },
             Version::V2 => {
                 VersionSpecificString::Offset(r.read_u64::<Endian>()?)
@@ -554,10 +610,7 @@ impl StringBlk {
     fn from_reader<R: ReadBytesExt>(r: &mut R) -> Result<Self, MMVDumpError> {
         let mut bytes = [0; STRING_BLOCK_LEN as usize];
         r.read_exact(&mut bytes)?;
-        let cstr = unsafe {
-            CStr::from_ptr(bytes.as_ptr() as *const i8)
-        };
-        let string = cstr.to_str()?.to_owned();
+        let string = string_from_cstr(&bytes)?;
 
         Ok(StringBlk {
             string: string
@@ -573,7 +626,7 @@ macro_rules! blks_from_toc (
             $cursor.set_position(toc.sec_offset);
             for _ in 0..toc.entries as usize {
                 let blk_offset = $cursor.position();
-                let blk = $blk_typ::from_reader(&mut $cursor)?;
+                let blk = $blk_typ::from_reader(&mut *$cursor)?;
                 blks.insert(blk_offset, blk);
             }
 
@@ -589,7 +642,7 @@ macro_rules! blks_from_toc (
             $cursor.set_position(toc.sec_offset);
             for _ in 0..toc.entries as usize {
                 let blk_offset = $cursor.position();
-                let blk = $blk_typ::from_reader(&mut $cursor, $mmv_ver)?;
+                let blk = $blk_typ::from_reader(&mut *$cursor, $mmv_ver)?;
                 blks.insert(blk_offset, blk);
             }
 
@@ -600,6 +653,10 @@ macro_rules! blks_from_toc (
     };
 );
 
+// declared after the macros above, which it uses
+mod reader;
+pub use self::reader::{MMVReader, Snapshot};
+
 /// Returns an `MMV` structure by reading and parsing the MMV
 /// file stored at `mmv_path`
 pub fn dump(mmv_path: &Path) -> Result<MMV, MMVDumpError> {
@@ -607,9 +664,12 @@ pub fn dump(mmv_path: &Path) -> Result<MMV, MMVDumpError> {
     let mut file = File::open(mmv_path)?;
     file.read_to_end(&mut mmv_bytes)?;
 
-    let mut cursor = Cursor::new(mmv_bytes);
-    
-    let hdr = Header::from_reader(&mut cursor)?;
+    parse(&mut Cursor::new(mmv_bytes))
+}
+
+// parses an MMV from it's bytes
+fn parse<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<MMV, MMVDumpError> {
+    let hdr = Header::from_reader(cursor)?;
 
     let mut indom_toc = None;
     let mut instance_toc = None;
@@ -617,12 +677,26 @@ pub fn dump(mmv_path: &Path) -> Result<MMV, MMVDumpError> {
     let mut value_toc = None;
     let mut string_toc = None;
 
+    let mmv_len = cursor.get_ref().as_ref().len() as u64;
+    let secs_start = HDR_LEN + hdr.toc_count as u64 * TOC_BLOCK_LEN;
+
     for i in 0..hdr.toc_count {
         let toc_position = cursor.position();
-        let mut toc = TocBlk::from_reader(&mut cursor)?;
+        let mut toc = TocBlk::from_reader(cursor)?;
         toc._toc_index = i;
         toc._mmv_offset = toc_position;
 
+        let sec_end = (toc.entries as u64).checked_mul(blk_len(toc.sec, hdr.version))
+            .and_then(|sec_len| toc.sec_offset.checked_add(sec_len));
+        match sec_end {
+            Some(sec_end) if toc.sec_offset >= secs_start && sec_end <= mmv_len => {},
+            _ => return Err(MMVDumpError::SectionOutOfBounds {
+                toc_offset: toc_position,
+                sec_offset: toc.sec_offset,
+                entries: toc.entries
+            })
+        }
+
         if toc.sec == INDOM_TOC_CODE { indom_toc = Some(toc); }
         else if toc.sec == INSTANCE_TOC_CODE { instance_toc = Some(toc); }
         else if toc.sec == METRIC_TOC_CODE { metric_toc = Some(toc); }
@@ -643,19 +717,193 @@ pub fn dump(mmv_path: &Path) -> Result<MMV, MMVDumpError> {
     let value_blks = blks_from_toc!(value_toc, ValueBlk, cursor);
     let string_blks = blks_from_toc!(string_toc, StringBlk, cursor);
 
-    Ok(
-        MMV {
-            header: hdr,
-            metric_toc: metric_toc.unwrap(),
-            value_toc: value_toc.unwrap(),
-            string_toc: string_toc,
-            indom_toc: indom_toc,
-            instance_toc: instance_toc,
-            indom_blks: indom_blks,
-            instance_blks: instance_blks,
-            metric_blks: metric_blks,
-            value_blks: value_blks,
-            string_blks: string_blks
+    let mmv = MMV {
+        header: hdr,
+        metric_toc: metric_toc.unwrap(),
+        value_toc: value_toc.unwrap(),
+        string_toc: string_toc,
+        indom_toc: indom_toc,
+        instance_toc: instance_toc,
+        indom_blks: indom_blks,
+        instance_blks: instance_blks,
+        metric_blks: metric_blks,
+        value_blks: value_blks,
+        string_blks: string_blks
+    };
+
+    validate(&mmv)?;
+    Ok(mmv)
+}
+
+// length of a block in the given section
+fn blk_len(sec: u32, ver: Version) -> u64 {
+    match (sec, ver) {
+        (INDOM_TOC_CODE, _) => INDOM_BLOCK_LEN,
+        (INSTANCE_TOC_CODE, Version::V1) => INSTANCE_BLOCK_LEN_MMV1,
+        (INSTANCE_TOC_CODE, Version::V2) => INSTANCE_BLOCK_LEN_MMV2,
+        (METRIC_TOC_CODE, Version::V1) => METRIC_BLOCK_LEN_MMV1,
+        (METRIC_TOC_CODE, Version::V2) => METRIC_BLOCK_LEN_MMV2,
+        (VALUES_TOC_CODE, _) => VALUE_BLOCK_LEN,
+        (STRINGS_TOC_CODE, _) => STRING_BLOCK_LEN,
+        _ => 0
+    }
+}
+
+// checks that every offset in the blocks refers to a block of the right type
+fn validate(mmv: &MMV) -> Result<(), MMVDumpError> {
+    let check_string = |blk_offset: u64, offset: &Option<u64>| {
+        match *offset {
+            Some(offset) if !mmv.string_blks.contains_key(&offset) =>
+                Err(MMVDumpError::InvalidStringOffset { blk_offset: blk_offset, offset: offset }),
+            _ => Ok(())
         }
-    )
+    };
+    let check_version_specific_string = |blk_offset: u64, string: &VersionSpecificString| {
+        match *string {
+            VersionSpecificString::Offset(offset) => check_string(blk_offset, &Some(offset)),
+            VersionSpecificString::String(_) => Ok(())
+        }
+    };
+
+    for (&offset, indom) in &mmv.indom_blks {
+        if let Some(instances_offset) = indom.instances_offset {
+            if !mmv.instance_blks.contains_key(&instances_offset) {
+                return Err(MMVDumpError::InvalidInstanceOffset {
+                    blk_offset: offset,
+                    offset: instances_offset
+                });
+            }
+        }
+        check_string(offset, &indom.short_help_offset)?;
+        check_string(offset, &indom.long_help_offset)?;
+    }
+
+    for (&offset, instance) in &mmv.instance_blks {
+        if let Some(indom_offset) = instance.indom_offset {
+            if !mmv.indom_blks.contains_key(&indom_offset) {
+                return Err(MMVDumpError::InvalidIndomOffset {
+                    blk_offset: offset,
+                    offset: indom_offset
+                });
+            }
+        }
+        check_version_specific_string(offset, &instance.external_id)?;
+    }
+
+    for (&offset, metric) in &mmv.metric_blks {
+        check_version_specific_string(offset, &metric.name)?;
+        if let Some(indom) = metric.indom {
+            if !mmv.indom_blks.values().any(|indom_blk| indom_blk.indom == Some(indom)) {
+                return Err(MMVDumpError::UnknownIndom { blk_offset: offset, indom: indom });
+            }
+        }
+        check_string(offset, &metric.short_help_offset)?;
+        check_string(offset, &metric.long_help_offset)?;
+    }
+
+    for (&offset, value) in &mmv.value_blks {
+        if let Some(metric_offset) = value.metric_offset {
+            if !mmv.metric_blks.contains_key(&metric_offset) {
+                return Err(MMVDumpError::InvalidMetricOffset {
+                    blk_offset: offset,
+                    offset: metric_offset
+                });
+            }
+        }
+        if let Some(instance_offset) = value.instance_offset {
+            if !mmv.instance_blks.contains_key(&instance_offset) {
+                return Err(MMVDumpError::InvalidInstanceOffset {
+                    blk_offset: offset,
+                    offset: instance_offset
+                });
+            }
+        }
+        check_string(offset, &value.string_offset)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_validation() {
+    use super::client::Client;
+    use super::client::metric::{Indom, InstanceMetric, Metric, Semantics, Unit};
+    use byteorder::WriteBytesExt;
+
+    let mut metric = Metric::new("validation_metric", 1u32, Semantics::Instant,
+        Unit::new(), "help", "").unwrap();
+    let indom = Indom::new(&["a", "b"], "", "").unwrap();
+    let mut im = InstanceMetric::new(&indom, "validation_instance_metric", 0u32,
+        Semantics::Instant, Unit::new(), "", "").unwrap();
+
+    let client = Client::new("validation_test").unwrap();
+    client.export(&mut [&mut metric, &mut im]).unwrap();
+
+    let mut mmv_bytes = Vec::new();
+    File::open(client.mmv_path()).unwrap().read_to_end(&mut mmv_bytes).unwrap();
+    let mmv = parse(&mut Cursor::new(&mmv_bytes)).unwrap();
+
+    let write_u64 = |bytes: &mut Vec<u8>, offset: u64, val: u64| {
+        let mut cursor = Cursor::new(bytes);
+        cursor.set_position(offset);
+        cursor.write_u64::<Endian>(val).unwrap();
+    };
+
+    // truncated
+    let truncated = &mmv_bytes[..mmv_bytes.len() - 1];
+    match parse(&mut Cursor::new(truncated)) {
+        Err(MMVDumpError::SectionOutOfBounds { .. }) => {},
+        _ => panic!("truncated MMV parsed")
+    }
+
+    // value referring to a non-existent metric
+    let value_offset = *mmv.value_blks().keys().next().unwrap();
+    let mut corrupt = mmv_bytes.clone();
+    write_u64(&mut corrupt, value_offset + 16, 12345);
+    match parse(&mut Cursor::new(&corrupt)) {
+        Err(MMVDumpError::InvalidMetricOffset { blk_offset, offset }) => {
+            assert_eq!(blk_offset, value_offset);
+            assert_eq!(offset, 12345);
+        },
+        _ => panic!("dangling metric offset parsed")
+    }
+
+    // metric with a non-existent help text
+    let (&metric_offset, _) = mmv.metric_blks().iter()
+        .find(|&(_, blk)| blk.short_help_offset().is_some()).unwrap();
+    let short_help_pos = match mmv.header().version() {
+        Version::V1 => MMV1_NAME_MAX_LEN + 24,
+        Version::V2 => 32
+    };
+    let mut corrupt = mmv_bytes.clone();
+    write_u64(&mut corrupt, metric_offset + short_help_pos, 12345);
+    match parse(&mut Cursor::new(&corrupt)) {
+        Err(MMVDumpError::InvalidStringOffset { blk_offset, offset }) => {
+            assert_eq!(blk_offset, metric_offset);
+            assert_eq!(offset, 12345);
+        },
+        _ => panic!("dangling string offset parsed")
+    }
+
+    // value referring to a non-existent instance
+    let (&value_offset, value) = mmv.value_blks().iter()
+        .find(|&(_, blk)| blk.instance_offset().is_some()).unwrap();
+    let instance_offset = value.instance_offset().unwrap();
+    let mut corrupt = mmv_bytes.clone();
+    write_u64(&mut corrupt, value_offset + 24, instance_offset + 1);
+    match parse(&mut Cursor::new(&corrupt)) {
+        Err(MMVDumpError::InvalidInstanceOffset { blk_offset, .. }) => assert_eq!(blk_offset, value_offset),
+        _ => panic!("dangling instance offset parsed")
+    }
+
+    // string without a null terminator
+    let string_offset = *mmv.string_blks().keys().next().unwrap() as usize;
+    let mut corrupt = mmv_bytes.clone();
+    for byte in &mut corrupt[string_offset..string_offset + STRING_BLOCK_LEN as usize] {
+        *byte = b'a';
+    }
+    match parse(&mut Cursor::new(&corrupt)) {
+        Err(MMVDumpError::UnterminatedString) => {},
+        _ => panic!("unterminated string parsed")
+    }
 }
@@ -0,0 +1,301 @@
+use std::fs::{self, File};
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use super::*;
+
+// offsets of the generation numbers in the MMV header
+const GEN1_OFF: usize = 8;
+const GEN2_OFF: usize = 16;
+const GEN_END_OFF: usize = 24;
+
+const MAX_READ_ATTEMPTS: usize = 100;
+
+/// Reader of a live MMV, for polling the values of it's metrics
+///
+/// The MMV file is kept open and it's metadata is parsed only once, so
+/// that each poll only reads the value section and the string blocks
+/// that the values point to.
+///
+/// Blocks are read with positioned reads rather than memory-mapped, so
+/// an MMV that's truncated by another process while being read is an
+/// error rather than a `SIGBUS`.
+///
+/// Reads are checked against the generation numbers in the MMV header,
+/// and are retried while a writer is updating the MMV, so that every
+/// snapshot of the values is consistent. If the MMV was regenerated,
+/// it's opened and parsed again.
+pub struct MMVReader {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    mmv: MMV
+}
+
+/// Values of an MMV read at once by an `MMVReader`
+///
+/// The blocks are keyed by their offsets in the MMV, just like in `MMV`.
+pub struct Snapshot {
+    gen: i64,
+    value_blks: BTreeMap<u64, ValueBlk>,
+    string_blks: BTreeMap<u64, StringBlk>
+}
+
+impl Snapshot {
+    /// Generation of the MMV the values were read from
+    pub fn gen(&self) -> i64 { self.gen }
+    /// Value blocks
+    pub fn value_blks(&self) -> &BTreeMap<u64, ValueBlk> { &self.value_blks }
+    /// String blocks holding the values of string metrics
+    pub fn string_blks(&self) -> &BTreeMap<u64, StringBlk> { &self.string_blks }
+}
+
+impl MMVReader {
+    /// Opens and parses the MMV file stored at `mmv_path`
+    pub fn open(mmv_path: &Path) -> Result<Self, MMVDumpError> {
+        let (file, len, mmv) = open_and_parse(mmv_path)?;
+        Ok(MMVReader {
+            path: mmv_path.to_owned(),
+            file: file,
+            len: len,
+            mmv: mmv
+        })
+    }
+
+    /// Returns the metadata of the MMV, as of the last read
+    ///
+    /// The value and string blocks are those read when the MMV was parsed.
+    pub fn mmv(&self) -> &MMV { &self.mmv }
+
+    /// Returns the path of the MMV file
+    pub fn path(&self) -> &Path { &self.path }
+
+    /// Reads a consistent snapshot of the values
+    ///
+    /// The result is an `Io` error if the MMV file was removed, or an
+    /// `InvalidMMV` error if it shrunk or a consistent snapshot couldn't
+    /// be read after retrying.
+    pub fn read(&mut self) -> Result<Snapshot, MMVDumpError> {
+        for _ in 0..MAX_READ_ATTEMPTS {
+            // a new MMV was renamed into place
+            if self.replaced()? {
+                let (file, len, mmv) = open_and_parse(&self.path)?;
+                self.file = file;
+                self.len = len;
+                self.mmv = mmv;
+                continue;
+            }
+
+            let gen = match self.stable_gen()? {
+                Some(gen) => gen,
+                None => {
+                    thread::yield_now();
+                    continue;
+                }
+            };
+
+            let snapshot = self.read_values(gen)?;
+            if self.stable_gen()? != Some(gen) {
+                continue;
+            }
+
+            // the generation changes when values are updated in a batch
+            self.mmv.header.gen1 = gen;
+            self.mmv.header.gen2 = gen;
+            return Ok(snapshot);
+        }
+
+        return_mmvdumperror!("Couldn't read a consistent snapshot", self.path.display());
+    }
+
+    // checks if the file at the path isn't the one that's open
+    #[cfg(unix)]
+    fn replaced(&self) -> Result<bool, MMVDumpError> {
+        use std::os::unix::fs::MetadataExt;
+
+        let path_meta = fs::metadata(&self.path)?;
+        let file_meta = self.file.metadata()?;
+        Ok(path_meta.dev() != file_meta.dev() || path_meta.ino() != file_meta.ino())
+    }
+
+    // checks if the file at the path isn't the one that's open, by
+    // comparing their generations, since file IDs aren't available
+    #[cfg(not(unix))]
+    fn replaced(&self) -> Result<bool, MMVDumpError> {
+        let path_file = File::open(&self.path)?;
+        let path_gen = read_gens(&path_file, &self.path)?.0;
+        Ok(path_gen != read_gens(&self.file, &self.path)?.0)
+    }
+
+    // returns the generation of the open MMV, if no writer is updating it
+    fn stable_gen(&self) -> Result<Option<i64>, MMVDumpError> {
+        let (gen1, gen2) = read_gens(&self.file, &self.path)?;
+        Ok(if gen1 == gen2 { Some(gen1) } else { None })
+    }
+
+    // reads the value section, and the string blocks the values point to
+    fn read_values(&self, gen: i64) -> Result<Snapshot, MMVDumpError> {
+        let sec_offset = self.mmv.value_toc.sec_offset;
+        let mut bytes = vec![0; (self.mmv.value_toc.entries as u64 * VALUE_BLOCK_LEN) as usize];
+        read_exact_at(&self.file, &mut bytes, sec_offset, &self.path)?;
+        let mut cursor = Cursor::new(&bytes);
+
+        let mut value_blks = BTreeMap::new();
+        let mut string_blks = BTreeMap::new();
+        for &offset in self.mmv.value_blks.keys() {
+            cursor.set_position(offset - sec_offset);
+            let value_blk = ValueBlk::from_reader(&mut cursor)?;
+            if let Some(string_offset) = value_blk.string_offset {
+                if string_offset + STRING_BLOCK_LEN > self.len {
+                    return_mmvdumperror!("String offset out of bounds", string_offset);
+                }
+                let mut string_bytes = [0; STRING_BLOCK_LEN as usize];
+                read_exact_at(&self.file, &mut string_bytes, string_offset, &self.path)?;
+                let string_blk = StringBlk::from_reader(&mut Cursor::new(&string_bytes[..]))?;
+                string_blks.insert(string_offset, string_blk);
+            }
+            value_blks.insert(offset, value_blk);
+        }
+
+        Ok(Snapshot {
+            gen: gen,
+            value_blks: value_blks,
+            string_blks: string_blks
+        })
+    }
+}
+
+// reads both generation numbers from the header of the MMV
+fn read_gens(file: &File, path: &Path) -> Result<(i64, i64), MMVDumpError> {
+    let mut gen_bytes = [0; GEN_END_OFF];
+    read_exact_at(file, &mut gen_bytes, 0, path)?;
+    let mut cursor = Cursor::new(&gen_bytes[GEN1_OFF..]);
+    let gen1 = cursor.read_i64::<Endian>()?;
+    cursor.set_position((GEN2_OFF - GEN1_OFF) as u64);
+    let gen2 = cursor.read_i64::<Endian>()?;
+    Ok((gen1, gen2))
+}
+
+// fills `buf` from the given offset of the file, which is an `InvalidMMV`
+// error if the file ends before it's filled, i.e., if it shrunk
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64, path: &Path)
+    -> Result<(), MMVDumpError> {
+    while !buf.is_empty() {
+        match read_at(file, buf, offset) {
+            Ok(0) => {
+                return_mmvdumperror!("MMV shrunk", path.display());
+            },
+            Ok(n) => {
+                let tmp = buf;
+                buf = &mut tmp[n..];
+                offset += n as u64;
+            },
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(MMVDumpError::Io(err))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    file.seek_read(buf, offset)
+}
+
+fn open_and_parse(mmv_path: &Path) -> Result<(File, u64, MMV), MMVDumpError> {
+    for _ in 0..MAX_READ_ATTEMPTS {
+        let file = File::open(mmv_path)?;
+        let len = file.metadata()?.len();
+        if len < GEN_END_OFF as u64 {
+            return_mmvdumperror!("MMV too short", mmv_path.display());
+        }
+
+        let mut bytes = vec![0; len as usize];
+        read_exact_at(&file, &mut bytes, 0, mmv_path)?;
+        match parse(&mut Cursor::new(&bytes)) {
+            Ok(mmv) => return Ok((file, len, mmv)),
+            Err(err) => {
+                // only retry if a writer is updating the MMV
+                let (gen1, gen2) = read_gens(&file, mmv_path)?;
+                if gen1 == gen2 {
+                    return Err(err);
+                }
+                thread::yield_now();
+            }
+        }
+    }
+    Err(MMVDumpError::Io(io::Error::new(io::ErrorKind::TimedOut,
+        "MMV kept being updated while parsing")))
+}
+
+#[test]
+fn test() {
+    use super::super::client::Client;
+    use super::super::client::metric::{Counter, Metric, Semantics, Unit};
+    use std::fs;
+    use std::io::Write;
+
+    let mut counter = Counter::new("reader_counter", 1, "", "").unwrap();
+    let mut string = Metric::new("reader_string", "a".to_owned(),
+        Semantics::Discrete, Unit::new(), "", "").unwrap();
+
+    let client = Client::new("reader_test").unwrap();
+    client.export(&mut [&mut counter, &mut string]).unwrap();
+
+    let mut reader = MMVReader::open(client.mmv_path()).unwrap();
+    assert_eq!(reader.mmv().metric_blks().len(), 2);
+
+    let snapshot = reader.read().unwrap();
+    assert!(snapshot.value_blks().values().any(|v| v.value() == 1));
+    assert!(snapshot.string_blks().values().any(|s| s.string() == "a"));
+
+    counter.inc(41).unwrap();
+    string.set_val("b".to_owned()).unwrap();
+    let snapshot = reader.read().unwrap();
+    assert!(snapshot.value_blks().values().any(|v| v.value() == 42));
+    assert!(snapshot.string_blks().values().any(|s| s.string() == "b"));
+
+    // generation changes on batched updates
+    let mut batch = client.batch();
+    batch.update(|| Ok(counter.up()?));
+    batch.commit().unwrap();
+    let next_snapshot = reader.read().unwrap();
+    assert!(next_snapshot.gen() > snapshot.gen());
+    assert!(next_snapshot.value_blks().values().any(|v| v.value() == 43));
+
+    // a regenerated MMV is parsed again
+    let mut new_counter = Counter::new("reader_new_counter", 7, "", "").unwrap();
+    client.register(&mut new_counter).unwrap();
+    let snapshot = reader.read().unwrap();
+    assert_eq!(reader.mmv().metric_blks().len(), 3);
+    assert!(snapshot.value_blks().values().any(|v| v.value() == 7));
+
+    // a shrunk MMV is an error
+    let shrunk_path = client.mmv_path().with_file_name("reader_shrunk_test");
+    fs::copy(client.mmv_path(), &shrunk_path).unwrap();
+    let mut shrunk_reader = MMVReader::open(&shrunk_path).unwrap();
+    fs::OpenOptions::new().write(true).open(&shrunk_path).unwrap().set_len(10).unwrap();
+    assert!(shrunk_reader.read().is_err());
+
+    // a removed MMV is an error
+    fs::remove_file(&shrunk_path).unwrap();
+    assert!(shrunk_reader.read().is_err());
+
+    // an invalid MMV that isn't being updated isn't retried
+    let invalid_path = client.mmv_path().with_file_name("reader_invalid_test");
+    fs::copy(client.mmv_path(), &invalid_path).unwrap();
+    fs::OpenOptions::new().write(true).open(&invalid_path).unwrap().write_all(b"X").unwrap();
+    match MMVReader::open(&invalid_path) {
+        Err(MMVDumpError::InvalidMMV(_)) => {},
+        _ => panic!("expected an InvalidMMV error")
+    }
+    fs::remove_file(&invalid_path).unwrap();
+}
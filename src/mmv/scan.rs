@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::*;
+use super::super::client::{get_mmv_dir, MMVFlags};
+
+/// Summary of an MMV found by `scan`
+pub struct MMVSummary {
+    version: Version,
+    gen: i64,
+    pid: i32,
+    cluster_id: u32,
+    flags: MMVFlags,
+    metrics: usize
+}
+
+impl MMVSummary {
+    fn from_mmv(mmv: &MMV) -> Self {
+        MMVSummary {
+            version: mmv.header.version,
+            gen: mmv.header.gen1,
+            pid: mmv.header.pid,
+            cluster_id: mmv.header.cluster_id,
+            flags: MMVFlags::from_bits_truncate(mmv.header.flags),
+            metrics: mmv.metric_blks.len()
+        }
+    }
+
+    /// MMV version
+    pub fn version(&self) -> Version { self.version }
+    /// Generation of the MMV
+    pub fn gen(&self) -> i64 { self.gen }
+    /// PID of the process that produced the MMV
+    pub fn pid(&self) -> i32 { self.pid }
+    /// Cluster ID
+    pub fn cluster_id(&self) -> u32 { self.cluster_id }
+    /// Flags the MMV was exported with
+    pub fn flags(&self) -> MMVFlags { self.flags }
+    /// Number of metrics in the MMV
+    pub fn metrics(&self) -> usize { self.metrics }
+}
+
+/// Lists the MMV files in `mmv_dir`, along with a summary of each, or the
+/// error encountered while parsing it
+///
+/// Temporary files of clients that are writing an MMV, whose names start
+/// with a `.`, aren't listed. The files are sorted by their paths.
+pub fn scan_dir(mmv_dir: &Path) -> io::Result<Vec<(PathBuf, Result<MMVSummary, MMVDumpError>)>> {
+    Ok(
+        mmv_files(mmv_dir)?.into_iter()
+            .map(|path| {
+                let summary = dump(&path).map(|mmv| MMVSummary::from_mmv(&mmv));
+                (path, summary)
+            })
+            .collect()
+    )
+}
+
+/// Lists the MMV files in the directory clients export MMVs to,
+/// as with `scan_dir`
+pub fn scan() -> io::Result<Vec<(PathBuf, Result<MMVSummary, MMVDumpError>)>> {
+    scan_dir(&get_mmv_dir()?)
+}
+
+// returns the paths of the regular files in the MMV directory,
+// except for temporary ones
+pub (super) fn mmv_files(mmv_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(mmv_dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file()
+            && !entry.file_name().to_string_lossy().starts_with('.') {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[test]
+fn test() {
+    use super::super::client::{Client, PROCESS};
+    use super::super::client::metric::Counter;
+
+    let mut a = Counter::new("scan_a", 0, "", "").unwrap();
+    let mut b = Counter::new("scan_b", 0, "", "").unwrap();
+    let client = Client::new_custom("scan_test", PROCESS, 7).unwrap();
+    client.export(&mut [&mut a, &mut b]).unwrap();
+
+    let mmv_dir = client.mmv_path().parent().unwrap().join("scan_test_dir");
+    fs::create_dir_all(&mmv_dir).unwrap();
+    let mmv_path = mmv_dir.join("exported");
+    fs::copy(client.mmv_path(), &mmv_path).unwrap();
+    let invalid_path = mmv_dir.join("invalid");
+    File::create(&invalid_path).unwrap().write_all(b"not an MMV").unwrap();
+    File::create(mmv_dir.join(".exported.tmp")).unwrap();
+
+    let scanned = scan_dir(&mmv_dir).unwrap();
+    assert_eq!(scanned.len(), 2);
+
+    assert_eq!(scanned[0].0, mmv_path);
+    let summary = scanned[0].1.as_ref().unwrap();
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert_eq!(summary.version() as u32, mmv.header().version() as u32);
+    assert_eq!(summary.gen(), mmv.header().gen1());
+    assert_eq!(summary.pid(), mmv.header().pid());
+    assert_eq!(summary.cluster_id(), 7);
+    assert_eq!(summary.flags(), PROCESS);
+    assert_eq!(summary.metrics(), 2);
+
+    assert_eq!(scanned[1].0, invalid_path);
+    assert!(scanned[1].1.is_err());
+
+    assert!(scan().unwrap().iter().any(|&(ref path, _)| path == client.mmv_path()));
+
+    fs::remove_dir_all(&mmv_dir).unwrap();
+}
@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::*;
+use super::scan::mmv_files;
+use super::super::client::{get_mmv_dir, MMVFlags, PROCESS};
+
+/// State of the process that produced an MMV, as given by the `pid`
+/// in it's header
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Producer {
+    /// Process is still running
+    Alive,
+    /// No process with the PID is running
+    Dead,
+    /// PID belongs to a process that started after the MMV was generated,
+    /// i.e., the producer died and it's PID was reused
+    PidReused,
+    /// MMV doesn't have the `PROCESS` flag, so it's producer isn't tracked
+    Untracked,
+    /// Producer can't be checked on this platform
+    Unknown
+}
+
+impl Producer {
+    /// Checks if the producer has died, and so the MMV is stale
+    pub fn is_stale(&self) -> bool {
+        *self == Producer::Dead || *self == Producer::PidReused
+    }
+}
+
+impl MMV {
+    /// Returns the state of the process that produced the MMV
+    pub fn producer(&self) -> io::Result<Producer> {
+        if !MMVFlags::from_bits_truncate(self.header.flags).contains(PROCESS) {
+            return Ok(Producer::Untracked);
+        }
+        producer_state(self.header.pid, self.header.gen1)
+    }
+}
+
+// seconds a producer's start time may be past the generation before
+// it's taken as a different process, since the start time is derived
+// from the boot time and clock ticks, both of which are rounded
+#[cfg(target_os = "linux")]
+const START_TIME_TOLERANCE: i64 = 5;
+
+#[cfg(target_os = "linux")]
+fn producer_state(pid: i32, gen: i64) -> io::Result<Producer> {
+    use super::super::client::metric::process_start_time;
+
+    if pid <= 0 {
+        return Ok(Producer::Dead);
+    }
+    // the generation is the time the MMV was generated at, and the
+    // producer must have started before it
+    match process_start_time(pid)? {
+        Some(start_time) if start_time > gen.saturating_add(START_TIME_TOLERANCE) =>
+            Ok(Producer::PidReused),
+        Some(_) => Ok(Producer::Alive),
+        None => Ok(Producer::Dead)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn producer_state(_pid: i32, _gen: i64) -> io::Result<Producer> {
+    Ok(Producer::Unknown)
+}
+
+/// Stale MMV files removed by `clean`, and the ones it failed to remove
+pub struct Cleanup {
+    removed: Vec<PathBuf>,
+    errors: Vec<(PathBuf, io::Error)>
+}
+
+impl Cleanup {
+    /// Paths of the removed MMV files
+    pub fn removed(&self) -> &[PathBuf] { &self.removed }
+    /// Paths of the MMV files that couldn't be removed, with the errors
+    pub fn errors(&self) -> &[(PathBuf, io::Error)] { &self.errors }
+}
+
+/// Removes the MMV files in `mmv_dir` whose producers have died
+///
+/// Files that aren't valid MMVs, files whose producers can't be checked,
+/// and temporary files of clients that are writing an MMV (whose names
+/// start with a `.`), are left alone. So are stale files that were
+/// already removed, or that this process isn't permitted to remove.
+/// Failing to remove any other stale file doesn't stop the others from
+/// being removed, and the error is returned in the `Cleanup`.
+pub fn clean(mmv_dir: &Path) -> io::Result<Cleanup> {
+    let mut removed = Vec::new();
+    let mut errors = Vec::new();
+    for path in mmv_files(mmv_dir)? {
+        // files whose producers can't be checked are kept
+        let stale = match dump(&path) {
+            Ok(mmv) => mmv.producer().map(|producer| producer.is_stale()).unwrap_or(false),
+            Err(_) => false
+        };
+        if stale {
+            match fs::remove_file(&path) {
+                Ok(_) => removed.push(path),
+                // removed by it's producer or another janitor, or
+                // can't be removed by this process
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound
+                    || err.kind() == io::ErrorKind::PermissionDenied => {},
+                Err(err) => errors.push((path, err))
+            }
+        }
+    }
+    Ok(Cleanup {
+        removed: removed,
+        errors: errors
+    })
+}
+
+/// Removes the stale MMV files in the directory clients export MMVs to,
+/// as with `clean`
+pub fn clean_mmv_dir() -> io::Result<Cleanup> {
+    clean(&get_mmv_dir()?)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test() {
+    use super::super::client::Client;
+    use super::super::client::metric::{process_start_time, Counter};
+    use byteorder::WriteBytesExt;
+    use std::fs::OpenOptions;
+    use std::io::SeekFrom;
+
+    let mut counter = Counter::new("stale_counter", 0, "", "").unwrap();
+    let client = Client::new("stale_test").unwrap();
+    client.export(&mut [&mut counter]).unwrap();
+
+    let mmv = dump(client.mmv_path()).unwrap();
+    assert_eq!(mmv.producer().unwrap(), Producer::Alive);
+
+    let mmv_dir = client.mmv_path().parent().unwrap().join("stale_test_dir");
+    fs::create_dir_all(&mmv_dir).unwrap();
+    let copy = |name: &str, pid: i32, gen: i64| {
+        let path = mmv_dir.join(name);
+        fs::copy(client.mmv_path(), &path).unwrap();
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(8)).unwrap();
+        file.write_i64::<Endian>(gen).unwrap();
+        file.write_i64::<Endian>(gen).unwrap();
+        file.seek(SeekFrom::Start(32)).unwrap();
+        file.write_i32::<Endian>(pid).unwrap();
+        path
+    };
+
+    let alive = copy("alive", mmv.header().pid(), mmv.header().gen1());
+    // PIDs are capped well below this
+    let dead = copy("dead", 1 << 30, mmv.header().gen1());
+    // init started after this generation
+    let reused = copy("reused", 1, 1);
+    // the start time is only precise to a few seconds
+    let start_time = process_start_time(mmv.header().pid()).unwrap().unwrap();
+    let skewed = copy("skewed", mmv.header().pid(), start_time - 3);
+    let invalid = mmv_dir.join("invalid");
+    File::create(&invalid).unwrap().write_all(b"not an MMV").unwrap();
+
+    assert_eq!(dump(&dead).unwrap().producer().unwrap(), Producer::Dead);
+    assert_eq!(dump(&reused).unwrap().producer().unwrap(), Producer::PidReused);
+    assert_eq!(dump(&skewed).unwrap().producer().unwrap(), Producer::Alive);
+
+    let cleanup = clean(&mmv_dir).unwrap();
+    assert!(cleanup.errors().is_empty());
+    let mut removed = cleanup.removed().to_vec();
+    removed.sort();
+    assert_eq!(removed, vec![dead.clone(), reused.clone()]);
+    assert!(alive.exists());
+    assert!(skewed.exists());
+    assert!(invalid.exists());
+    assert!(!dead.exists());
+    assert!(!reused.exists());
+
+    fs::remove_dir_all(&mmv_dir).unwrap();
+}
@@ -0,0 +1,207 @@
+use super::*;
+use super::super::client::metric::{Semantics, Unit};
+
+/// Value of a metric, typed by it's `MTCode`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// 32-bit signed integer
+    I32(i32),
+    /// 32-bit unsigned integer
+    U32(u32),
+    /// 64-bit signed integer
+    I64(i64),
+    /// 64-bit unsigned integer
+    U64(u64),
+    /// 32-bit float
+    F32(f32),
+    /// 64-bit double
+    F64(f64),
+    /// String
+    String(String)
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::I32(v) => write!(f, "{}", v),
+            Value::U32(v) => write!(f, "{}", v),
+            Value::I64(v) => write!(f, "{}", v),
+            Value::U64(v) => write!(f, "{}", v),
+            Value::F32(v) => write!(f, "{}", v),
+            Value::F64(v) => write!(f, "{}", v),
+            Value::String(ref v) => write!(f, "\"{}\"", v)
+        }
+    }
+}
+
+/// A metric of an `MMV`, with it's name, help text and values resolved
+/// from the blocks they're stored in
+pub struct MetricView<'a> {
+    mmv: &'a MMV,
+    offset: u64,
+    blk: &'a MetricBlk,
+    name: &'a str
+}
+
+impl<'a> MetricView<'a> {
+    /// Name of the metric
+    pub fn name(&self) -> &'a str { self.name }
+    /// Item ID of the metric
+    pub fn item(&self) -> Option<u32> { *self.blk.item() }
+    /// Type of the metric's value, if it's valid
+    pub fn mtcode(&self) -> Option<MTCode> { MTCode::from_u32(self.blk.typ()) }
+    /// Semantics of the metric, if they're valid
+    pub fn sem(&self) -> Option<Semantics> { Semantics::from_u32(self.blk.sem()) }
+    /// Unit of the metric
+    pub fn unit(&self) -> Unit { Unit::from_raw(self.blk.unit()) }
+    /// Instance domain ID of the metric, if it has one
+    pub fn indom(&self) -> Option<u32> { *self.blk.indom() }
+    /// Short help text of the metric
+    pub fn shorthelp(&self) -> Option<&'a str> { self.help(self.blk.short_help_offset()) }
+    /// Long help text of the metric
+    pub fn longhelp(&self) -> Option<&'a str> { self.help(self.blk.long_help_offset()) }
+    /// Raw metric block
+    pub fn blk(&self) -> &'a MetricBlk { self.blk }
+
+    /// Returns the values of the metric, along with the name of the
+    /// instance each is for, if the metric has an instance domain
+    ///
+    /// Values that can't be resolved, e.g., a string value whose
+    /// string block is absent, are skipped.
+    pub fn values(&self) -> Vec<(Option<&'a str>, Value)> {
+        let mut values = Vec::new();
+        for value_blk in self.mmv.value_blks().values() {
+            if *value_blk.metric_offset() != Some(self.offset) {
+                continue;
+            }
+
+            let instance = match *value_blk.instance_offset() {
+                Some(ref instance_offset) => {
+                    match self.mmv.instance_blks().get(instance_offset)
+                        .and_then(|instance| resolve_string(instance.external_id(), self.mmv)) {
+                        Some(instance) => Some(instance),
+                        None => continue
+                    }
+                },
+                None => None
+            };
+
+            if let Some(value) = self.resolve_value(value_blk) {
+                values.push((instance, value));
+            }
+        }
+        values
+    }
+
+    /// Returns the value of the metric for the given instance, or it's
+    /// only value if `instance` is `None`
+    pub fn value(&self, instance: Option<&str>) -> Option<Value> {
+        self.values().into_iter()
+            .find(|&(inst, _)| inst == instance)
+            .map(|(_, value)| value)
+    }
+
+    fn help(&self, offset: &Option<u64>) -> Option<&'a str> {
+        offset.and_then(|offset| self.mmv.string_blks().get(&offset))
+            .map(|string_blk| string_blk.string())
+    }
+
+    fn resolve_value(&self, value_blk: &ValueBlk) -> Option<Value> {
+        let raw = value_blk.value();
+        let value = match self.mtcode()? {
+            MTCode::I32 => Value::I32(raw as i32),
+            MTCode::U32 => Value::U32(raw as u32),
+            MTCode::I64 => Value::I64(raw as i64),
+            MTCode::U64 => Value::U64(raw),
+            MTCode::F32 => Value::F32(f32::from_bits(raw as u32)),
+            MTCode::F64 => Value::F64(f64::from_bits(raw)),
+            MTCode::String => {
+                let string_offset = (*value_blk.string_offset())?;
+                let string_blk = self.mmv.string_blks().get(&string_offset)?;
+                Value::String(string_blk.string().to_owned())
+            }
+        };
+        Some(value)
+    }
+}
+
+fn resolve_string<'a>(string: &'a VersionSpecificString, mmv: &'a MMV) -> Option<&'a str> {
+    match *string {
+        VersionSpecificString::String(ref string) => Some(string),
+        VersionSpecificString::Offset(ref offset) =>
+            mmv.string_blks().get(offset).map(|string_blk| string_blk.string())
+    }
+}
+
+impl MMV {
+    /// Returns the metrics of the MMV, ordered by their offsets
+    ///
+    /// Metrics without a valid item ID, or whose name can't be
+    /// resolved, are skipped.
+    pub fn metrics<'a>(&'a self) -> Vec<MetricView<'a>> {
+        self.metric_blks().iter()
+            .filter(|&(_, blk)| blk.item().is_some())
+            .filter_map(|(&offset, blk)| {
+                resolve_string(blk.name(), self).map(|name| MetricView {
+                    mmv: self,
+                    offset: offset,
+                    blk: blk,
+                    name: name
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the metric with the given name
+    pub fn metric<'a>(&'a self, name: &str) -> Option<MetricView<'a>> {
+        self.metrics().into_iter().find(|metric| metric.name() == name)
+    }
+
+    /// Returns the value of the given metric and instance
+    ///
+    /// `instance` is `None` for metrics without an instance domain.
+    pub fn get(&self, name: &str, instance: Option<&str>) -> Option<Value> {
+        self.metric(name).and_then(|metric| metric.value(instance))
+    }
+}
+
+#[test]
+fn test() {
+    use super::super::client::Client;
+    use super::super::client::metric::{Indom, InstanceMetric, Metric};
+
+    let mut count = Metric::new("products.count", -3i64, Semantics::Counter,
+        Unit::new(), "Product count", "").unwrap();
+    let mut price = Metric::new("products.price", 2.5f32, Semantics::Instant,
+        Unit::new(), "", "Product price").unwrap();
+    let mut label = Metric::new("products.label", "acme".to_owned(), Semantics::Discrete,
+        Unit::new(), "", "").unwrap();
+    let indom = Indom::new(&["Anvils", "Rockets"], "", "").unwrap();
+    let mut stock = InstanceMetric::new(&indom, "products.stock", 0u32,
+        Semantics::Instant, Unit::new(), "", "").unwrap();
+    stock.set_val("Anvils", 7).unwrap();
+
+    let client = Client::new("view_test").unwrap();
+    client.export(&mut [&mut count, &mut price, &mut label, &mut stock]).unwrap();
+    let mmv = dump(client.mmv_path()).unwrap();
+
+    assert_eq!(mmv.metrics().len(), 4);
+
+    let metric = mmv.metric("products.count").unwrap();
+    assert_eq!(metric.item(), Some(count.item()));
+    assert_eq!(metric.shorthelp(), Some("Product count"));
+    assert_eq!(metric.longhelp(), None);
+    assert!(metric.indom().is_none());
+    assert_eq!(metric.values(), vec![(None, Value::I64(-3))]);
+
+    assert_eq!(mmv.metric("products.price").unwrap().longhelp(), Some("Product price"));
+    assert_eq!(mmv.get("products.price", None), Some(Value::F32(2.5)));
+    assert_eq!(mmv.get("products.label", None), Some(Value::String("acme".to_owned())));
+
+    assert!(mmv.metric("products.stock").unwrap().indom().is_some());
+    assert_eq!(mmv.get("products.stock", Some("Anvils")), Some(Value::U32(7)));
+    assert_eq!(mmv.get("products.stock", Some("Rockets")), Some(Value::U32(0)));
+    assert_eq!(mmv.get("products.stock", Some("Hammers")), None);
+    assert_eq!(mmv.get("products.stock", None), None);
+    assert_eq!(mmv.get("products.weight", None), None);
+}